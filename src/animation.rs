@@ -0,0 +1,58 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use game::Move;
+use std::time::{Duration, Instant};
+
+/// Represents the animation of a move from one cell to the next.
+pub struct Animation {
+    /// The animated move
+    movement: Move,
+    /// The time at which the animation started
+    start: Instant,
+    /// The duration of the animation
+    duration: Duration,
+}
+
+impl Animation {
+    /// Starts animating the given move.
+    pub fn new(movement: Move, duration: Duration) -> Animation {
+        Animation {
+            movement,
+            start: Instant::now(),
+            duration,
+        }
+    }
+
+    /// Returns the animated move.
+    pub fn movement(&self) -> Move {
+        self.movement
+    }
+
+    /// Returns the progress of the animation between 0.0 and 1.0.
+    pub fn progress(&self) -> f64 {
+        let total = self.duration.as_secs_f64();
+        if total > 0.0 {
+            f64::min(1.0, self.start.elapsed().as_secs_f64() / total)
+        } else {
+            1.0
+        }
+    }
+
+    /// Returns true if the animation has come to an end.
+    pub fn is_finished(&self) -> bool {
+        self.start.elapsed() >= self.duration
+    }
+}
@@ -0,0 +1,111 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Locates the game assets (images and fonts).
+//!
+//! Relative asset paths are looked up in the following directories:
+//!
+//! 1. the directory given by the `SOKOBAN_RS_ASSETS` environment variable,
+//! 2. an `assets` directory next to the executable or in one of its parents,
+//! 3. a `sokoban-rs` directory in the XDG data directories,
+//! 4. the `assets` directory of the current working directory.
+//!
+//! When the `embed-assets` feature is enabled, the default assets are
+//! compiled into the binary and used if they are not found on disk.
+
+use dirs;
+use error::SokobanError;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The environment variable that points to the assets directory
+const ASSETS_ENV_VAR: &str = "SOKOBAN_RS_ASSETS";
+
+/// Represents an asset that has been located.
+pub enum Asset {
+    /// An asset stored in a file
+    File(PathBuf),
+    /// An asset compiled into the binary
+    Embedded(&'static [u8]),
+}
+
+/// Locates the asset at the given path.
+pub fn locate<P: AsRef<Path>>(path: P) -> Result<Asset, SokobanError> {
+    let path = path.as_ref();
+    if path.is_absolute() {
+        return if path.is_file() {
+            Ok(Asset::File(path.to_path_buf()))
+        } else {
+            Err(SokobanError::AssetNotFound(path.to_path_buf()))
+        };
+    }
+
+    search_dirs()
+        .into_iter()
+        .map(|dir| dir.join(path))
+        .find(|candidate| candidate.is_file())
+        .map(Asset::File)
+        .or_else(|| embedded(path).map(Asset::Embedded))
+        .ok_or_else(|| SokobanError::AssetNotFound(path.to_path_buf()))
+}
+
+/// Returns the directories in which assets are looked up, by order of preference.
+fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if let Some(dir) = env::var_os(ASSETS_ENV_VAR) {
+        dirs.push(PathBuf::from(dir));
+    }
+
+    if let Ok(exe) = env::current_exe() {
+        dirs.extend(exe.ancestors().skip(1).map(|dir| dir.join("assets")));
+    }
+
+    if let Some(dir) = dirs::data_dir() {
+        dirs.push(dir.join("sokoban-rs"));
+    }
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    dirs.extend(env::split_paths(&data_dirs).map(|dir| dir.join("sokoban-rs")));
+
+    dirs.push(PathBuf::from("assets"));
+    dirs
+}
+
+/// Returns the contents of a default asset compiled into the binary.
+#[cfg(feature = "embed-assets")]
+fn embedded(path: &Path) -> Option<&'static [u8]> {
+    match path.to_str()? {
+        "image/tileset.png" => Some(include_bytes!("../assets/image/tileset.png")),
+        "image/tileset-small.png" => Some(include_bytes!("../assets/image/tileset-small.png")),
+        "image/tileset-flat.png" => Some(include_bytes!("../assets/image/tileset-flat.png")),
+        "image/tileset-flat-small.png" => {
+            Some(include_bytes!("../assets/image/tileset-flat-small.png"))
+        }
+        "themes/planet-cute.toml" => Some(include_bytes!("../assets/themes/planet-cute.toml")),
+        "themes/flat.toml" => Some(include_bytes!("../assets/themes/flat.toml")),
+        "font/RujisHandwritingFontv.2.0.ttf" => Some(include_bytes!(
+            "../assets/font/RujisHandwritingFontv.2.0.ttf"
+        )),
+        _ => None,
+    }
+}
+
+/// Returns the contents of a default asset compiled into the binary.
+#[cfg(not(feature = "embed-assets"))]
+fn embedded(_path: &Path) -> Option<&'static [u8]> {
+    None
+}
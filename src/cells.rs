@@ -0,0 +1,250 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A compact set of cells for the walls, boxes and squares of a level.
+
+use std::slice;
+
+use game::Position;
+
+/// Represents a set of cells laid out as a dense grid.
+///
+/// Each cell of the rectangle covering the set has a slot, which holds the index
+/// of the cell in the list of members, plus one, or zero if the cell is not a
+/// member. Looking up, adding, removing and moving a cell take constant time, and
+/// cloning the set copies two flat vectors. The rectangle grows as cells are added
+/// outside of it.
+#[derive(Clone, Debug, Default)]
+pub struct CellSet {
+    /// The row and column of the top-left corner of the rectangle
+    origin: (i32, i32),
+    /// The number of rows and columns of the rectangle
+    size: (i32, i32),
+    /// The slots of the cells of the rectangle, row by row
+    slots: Vec<u32>,
+    /// The members of the set
+    cells: Vec<Position>,
+}
+
+impl CellSet {
+    /// Creates an empty set.
+    pub fn new() -> CellSet {
+        CellSet::default()
+    }
+
+    /// Returns the number of cells in the set.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Returns true if the set has no cell.
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// Returns true if the given cell is in the set.
+    pub fn contains(&self, pos: &Position) -> bool {
+        self.slot(pos).is_some_and(|slot| self.slots[slot] != 0)
+    }
+
+    /// Adds a cell to the set.
+    ///
+    /// Returns true if the cell was not in the set yet.
+    pub fn insert(&mut self, pos: Position) -> bool {
+        let slot = match self.slot(&pos) {
+            Some(slot) => slot,
+            None => {
+                self.cover(&pos);
+                self.slot(&pos)
+                    .expect("The cell lies within the grown rectangle")
+            }
+        };
+        if self.slots[slot] != 0 {
+            return false;
+        }
+        self.cells.push(pos);
+        self.slots[slot] = self.cells.len() as u32;
+        true
+    }
+
+    /// Removes a cell from the set.
+    ///
+    /// Returns true if the cell was in the set.
+    pub fn remove(&mut self, pos: &Position) -> bool {
+        let slot = match self.slot(pos) {
+            Some(slot) if self.slots[slot] != 0 => slot,
+            _ => return false,
+        };
+        let index = self.slots[slot] as usize - 1;
+        self.slots[slot] = 0;
+        self.cells.swap_remove(index);
+        if let Some(&moved) = self.cells.get(index) {
+            let moved_slot = self.slot(&moved).expect("Members lie within the rectangle");
+            self.slots[moved_slot] = index as u32 + 1;
+        }
+        true
+    }
+
+    /// Moves a cell of the set to another cell that is not in the set.
+    ///
+    /// Returns true if the cell was moved, which it is not if it was missing
+    /// from the set or if the other cell is already in it.
+    pub fn move_cell(&mut self, from: &Position, to: Position) -> bool {
+        let from_slot = match self.slot(from) {
+            Some(slot) if self.slots[slot] != 0 => slot,
+            _ => return false,
+        };
+        let to_slot = match self.slot(&to) {
+            Some(slot) if self.slots[slot] != 0 => return false,
+            Some(slot) => slot,
+            None => {
+                // Growing the rectangle moves the slots around
+                self.remove(from);
+                return self.insert(to);
+            }
+        };
+        let index = self.slots[from_slot];
+        self.slots[from_slot] = 0;
+        self.slots[to_slot] = index;
+        self.cells[index as usize - 1] = to;
+        true
+    }
+
+    /// Keeps only the cells for which the predicate returns true.
+    pub fn retain<F: FnMut(&Position) -> bool>(&mut self, mut keep: F) {
+        let removed: Vec<Position> = self
+            .cells
+            .iter()
+            .filter(|pos| !keep(pos))
+            .cloned()
+            .collect();
+        for pos in &removed {
+            self.remove(pos);
+        }
+    }
+
+    /// Returns an iterator over the cells of the set.
+    pub fn iter(&self) -> slice::Iter<'_, Position> {
+        self.cells.iter()
+    }
+
+    /// Returns the index of the slot of a cell, if it lies within the rectangle.
+    fn slot(&self, pos: &Position) -> Option<usize> {
+        let row = pos.row() - self.origin.0;
+        let column = pos.column() - self.origin.1;
+        if row < 0 || row >= self.size.0 || column < 0 || column >= self.size.1 {
+            return None;
+        }
+        Some((row * self.size.1 + column) as usize)
+    }
+
+    /// Grows the rectangle to cover the given cell.
+    fn cover(&mut self, pos: &Position) {
+        let (top, left) = if self.slots.is_empty() {
+            (pos.row(), pos.column())
+        } else {
+            (
+                self.origin.0.min(pos.row()),
+                self.origin.1.min(pos.column()),
+            )
+        };
+        let (bottom, right) = if self.slots.is_empty() {
+            (pos.row() + 1, pos.column() + 1)
+        } else {
+            (
+                (self.origin.0 + self.size.0).max(pos.row() + 1),
+                (self.origin.1 + self.size.1).max(pos.column() + 1),
+            )
+        };
+        self.origin = (top, left);
+        self.size = (bottom - top, right - left);
+        self.slots = vec![0; (self.size.0 * self.size.1) as usize];
+        for index in 0..self.cells.len() {
+            let slot = self
+                .slot(&self.cells[index])
+                .expect("Members lie within the rectangle");
+            self.slots[slot] = index as u32 + 1;
+        }
+    }
+}
+
+/// Sets are equal when they have the same cells, whatever the order they were added in.
+impl PartialEq for CellSet {
+    fn eq(&self, other: &CellSet) -> bool {
+        self.len() == other.len() && self.iter().all(|pos| other.contains(pos))
+    }
+}
+
+impl Eq for CellSet {}
+
+#[cfg(test)]
+mod tests {
+    use super::CellSet;
+    use game::Position;
+
+    fn set_of(cells: &[(i32, i32)]) -> CellSet {
+        let mut set = CellSet::new();
+        for &(row, column) in cells {
+            set.insert(Position::new(row, column));
+        }
+        set
+    }
+
+    #[test]
+    fn remove_keeps_the_moved_member_reachable() {
+        let mut set = set_of(&[(1, 1), (1, 2), (2, 3)]);
+        // The last member takes the place of the removed one
+        assert!(set.remove(&Position::new(1, 1)));
+        assert!(!set.contains(&Position::new(1, 1)));
+        assert!(set.contains(&Position::new(2, 3)));
+        assert!(set.remove(&Position::new(2, 3)));
+        assert!(!set.remove(&Position::new(2, 3)));
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&Position::new(1, 2)]);
+    }
+
+    #[test]
+    fn cover_keeps_the_members_when_growing() {
+        let mut set = set_of(&[(2, 2), (3, 4)]);
+        assert!(set.insert(Position::new(-1, -3)));
+        assert!(set.insert(Position::new(7, 9)));
+        for &(row, column) in &[(2, 2), (3, 4), (-1, -3), (7, 9)] {
+            assert!(set.contains(&Position::new(row, column)));
+        }
+        assert!(!set.contains(&Position::new(2, 3)));
+        assert!(!set.insert(Position::new(3, 4)));
+        assert_eq!(set.len(), 4);
+    }
+
+    #[test]
+    fn move_cell_grows_the_rectangle() {
+        let mut set = set_of(&[(1, 1), (2, 2)]);
+        assert!(set.move_cell(&Position::new(1, 1), Position::new(5, -2)));
+        assert!(!set.contains(&Position::new(1, 1)));
+        assert!(set.contains(&Position::new(5, -2)));
+        assert!(set.contains(&Position::new(2, 2)));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn move_cell_refuses_occupied_or_missing_cells() {
+        let mut set = set_of(&[(1, 1), (1, 2)]);
+        assert!(!set.move_cell(&Position::new(1, 1), Position::new(1, 2)));
+        assert!(!set.move_cell(&Position::new(3, 3), Position::new(1, 3)));
+        assert_eq!(set, set_of(&[(1, 2), (1, 1)]));
+        assert!(set.move_cell(&Position::new(1, 1), Position::new(1, 3)));
+        assert_eq!(set, set_of(&[(1, 2), (1, 3)]));
+    }
+}
@@ -0,0 +1,350 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use error::SokobanError;
+use game::Level;
+use grid::{Grid, HEXAGONAL, SQUARE};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use xml::reader::EventReader;
+use xml::reader::XmlEvent;
+use xml::writer::EmitterConfig;
+
+/// Represents a collection of levels.
+pub struct Collection {
+    /// The collection's title
+    title: String,
+    /// The levels of the collection
+    levels: Vec<Level>,
+    /// The other elements describing the collection in an SLC file, such as
+    /// `Description` or `Email`, along with their text
+    metadata: Vec<(String, String)>,
+    /// The attributes of the `LevelCollection` element of an SLC file, such as `Copyright`
+    collection_attributes: Vec<(String, String)>,
+    /// The attributes of the levels of an SLC file besides their id and size,
+    /// by the index of the level
+    level_attributes: Vec<Vec<(String, String)>>,
+}
+
+impl Collection {
+    /// Creates an empty collection.
+    pub fn new<S: Into<String>>(title: S) -> Collection {
+        Collection {
+            title: title.into(),
+            levels: Vec::new(),
+            metadata: Vec::new(),
+            collection_attributes: Vec::new(),
+            level_attributes: Vec::new(),
+        }
+    }
+
+    /// Builds levels from a level collection file, in the XSB format if its
+    /// extension is `xsb` or `txt`, in the Hexoban text layout if it is `hsb`,
+    /// and in the SLC format otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Collection, SokobanError> {
+        match text_grid(path.as_ref()) {
+            Some(grid) => Collection::load_text_file(path, grid),
+            None => Collection::load_slc_file(path),
+        }
+    }
+
+    /// Returns the grid of the levels stored in the given file, judging by its extension.
+    pub fn grid<P: AsRef<Path>>(path: P) -> &'static dyn Grid {
+        text_grid(path.as_ref()).unwrap_or(&SQUARE)
+    }
+
+    /// Builds levels from a level collection file in the SLC format.
+    pub fn load_slc_file<P: AsRef<Path>>(path: P) -> Result<Collection, SokobanError> {
+        let parser = {
+            let file = File::open(path.as_ref())?;
+            EventReader::new(BufReader::new(file))
+        };
+
+        let mut collection = Collection::new(String::new());
+        let mut reading_title = false;
+        let mut level_title = String::new();
+        let mut level_data = String::new();
+        let mut reading_level = false;
+        // The elements of the collection are nested in the root element
+        let mut depth = 0;
+        let mut reading_metadata = false;
+        for event in parser {
+            match event {
+                Ok(XmlEvent::StartElement {
+                    ref name,
+                    ref attributes,
+                    ..
+                }) => {
+                    depth += 1;
+                    let pairs = || {
+                        attributes
+                            .iter()
+                            .map(|attr| (attr.name.local_name.clone(), attr.value.clone()))
+                    };
+                    if name.local_name == "Title" {
+                        reading_title = true;
+                    } else if name.local_name == "L" {
+                        reading_level = true;
+                    } else if name.local_name == "Level" {
+                        if let Some(id) =
+                            attributes.iter().find(|&attr| attr.name.local_name == "Id")
+                        {
+                            level_title = id.value.clone();
+                        }
+                        let extra = pairs().filter(|(name, _)| {
+                            name != "Id" && name != "Width" && name != "Height"
+                        });
+                        collection.level_attributes.push(extra.collect());
+                    } else if name.local_name == "LevelCollection" {
+                        collection.collection_attributes = pairs().collect();
+                    } else if depth == 2 {
+                        reading_metadata = true;
+                        collection
+                            .metadata
+                            .push((name.local_name.clone(), String::new()));
+                    }
+                }
+                Ok(XmlEvent::EndElement { name }) => {
+                    depth -= 1;
+                    if name.local_name == "Title" {
+                        reading_title = false;
+                    } else if name.local_name == "L" {
+                        reading_level = false;
+                        level_data.push('\n');
+                    } else if name.local_name == "Level" {
+                        let mut level = Level::from_str(&level_data)?;
+                        level.set_title(level_title.clone());
+                        collection.levels.push(level);
+                        level_data.clear();
+                    } else if depth == 1 {
+                        reading_metadata = false;
+                    }
+                }
+                Ok(XmlEvent::Characters(ref data)) | Ok(XmlEvent::Whitespace(ref data)) => {
+                    if reading_title {
+                        collection.title.push_str(data.trim());
+                    } else if reading_level {
+                        level_data.push_str(data);
+                    } else if reading_metadata {
+                        if let Some((_, text)) = collection.metadata.last_mut() {
+                            text.push_str(data);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(collection)
+    }
+
+    /// Builds levels from a level collection file in the XSB format.
+    ///
+    /// Levels are separated by any line that is not a row of a level. A line
+    /// starting with `Title:` or `;` after a level gives its title, and a comment
+    /// line before the first level gives the title of the collection.
+    pub fn load_xsb_file<P: AsRef<Path>>(path: P) -> Result<Collection, SokobanError> {
+        Collection::load_text_file(path, &SQUARE)
+    }
+
+    /// Builds levels from a level collection file in the Hexoban text layout.
+    ///
+    /// Apart from the layout of the rows, the file is read like an XSB file.
+    pub fn load_hsb_file<P: AsRef<Path>>(path: P) -> Result<Collection, SokobanError> {
+        Collection::load_text_file(path, &HEXAGONAL)
+    }
+
+    /// Builds levels laid out on the given grid from a text file.
+    fn load_text_file<P: AsRef<Path>>(
+        path: P,
+        grid: &'static dyn Grid,
+    ) -> Result<Collection, SokobanError> {
+        let contents = fs::read_to_string(path.as_ref())?;
+        let mut title = String::new();
+        let mut levels: Vec<Level> = Vec::new();
+        let mut level_data = String::new();
+        let mut has_title = false;
+        for line in contents.lines().chain(Some("")) {
+            if is_xsb_row(line) {
+                level_data.push_str(line);
+                level_data.push('\n');
+                continue;
+            }
+            if !level_data.is_empty() {
+                let mut level = Level::parse(&level_data, grid)?;
+                level.set_title((levels.len() + 1).to_string());
+                levels.push(level);
+                level_data.clear();
+                has_title = false;
+            }
+
+            let line = line.trim();
+            let text = match line.strip_prefix("Title:") {
+                Some(text) => text,
+                None => match line.strip_prefix(';') {
+                    Some(text) => text,
+                    None => continue,
+                },
+            };
+            match levels.last_mut() {
+                Some(level) if !has_title => {
+                    level.set_title(text.trim());
+                    has_title = true;
+                }
+                None if title.is_empty() => title = text.trim().to_owned(),
+                _ => {}
+            }
+        }
+
+        let mut collection = Collection::new(title);
+        collection.levels = levels;
+        Ok(collection)
+    }
+
+    /// Writes the collection to a file, in the XSB format or in the Hexoban text
+    /// layout if its extension is `xsb`, `txt` or `hsb`, and in the SLC format otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SokobanError> {
+        let mut file = BufWriter::new(File::create(path.as_ref())?);
+        if text_grid(path.as_ref()).is_some() {
+            self.write_xsb(&mut file)?;
+        } else {
+            self.write_slc(&mut file)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Writes the collection in the XSB format.
+    fn write_xsb<W: Write>(&self, out: &mut W) -> Result<(), SokobanError> {
+        if !self.title.is_empty() {
+            writeln!(out, "; {}", self.title)?;
+            writeln!(out)?;
+        }
+        for level in &self.levels {
+            // Empty rows would be taken for the end of the level
+            for row in level.to_string().lines() {
+                writeln!(out, "{}", if row.is_empty() { "-" } else { row })?;
+            }
+            writeln!(out, "Title: {}", level.title())?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the collection in the SLC format.
+    fn write_slc<W: Write>(&self, out: &mut W) -> Result<(), SokobanError> {
+        use xml::writer::XmlEvent;
+
+        let mut writer = EmitterConfig::new().perform_indent(true).create_writer(out);
+        writer.write(XmlEvent::start_element("SokobanLevels"))?;
+        writer.write(XmlEvent::start_element("Title"))?;
+        writer.write(XmlEvent::characters(&self.title))?;
+        writer.write(XmlEvent::end_element())?;
+        for (name, text) in &self.metadata {
+            writer.write(XmlEvent::start_element(name.as_str()))?;
+            writer.write(XmlEvent::characters(text.trim()))?;
+            writer.write(XmlEvent::end_element())?;
+        }
+        let mut start = XmlEvent::start_element("LevelCollection");
+        for (name, value) in &self.collection_attributes {
+            start = start.attr(name.as_str(), value);
+        }
+        writer.write(start)?;
+        for (index, level) in self.levels.iter().enumerate() {
+            let (width, height) = level.extents();
+            let (width, height) = (width.to_string(), height.to_string());
+            let mut start = XmlEvent::start_element("Level")
+                .attr("Id", level.title())
+                .attr("Width", &width)
+                .attr("Height", &height);
+            for (name, value) in self.level_attributes.get(index).into_iter().flatten() {
+                start = start.attr(name.as_str(), value);
+            }
+            writer.write(start)?;
+            for row in level.to_string().lines() {
+                writer.write(XmlEvent::start_element("L"))?;
+                if !row.is_empty() {
+                    writer.write(XmlEvent::characters(row))?;
+                }
+                writer.write(XmlEvent::end_element())?;
+            }
+            writer.write(XmlEvent::end_element())?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+
+    /// Returns the title
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns the levels
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// Replaces the level at the given index.
+    pub fn replace(&mut self, index: usize, level: Level) {
+        self.levels[index] = level;
+    }
+
+    /// Adds a level at the end of the collection.
+    pub fn push(&mut self, level: Level) {
+        self.levels.push(level);
+    }
+
+    /// Sets an attribute written with the level at the given index in SLC files.
+    pub fn set_level_attribute<S: Into<String>>(&mut self, index: usize, name: &str, value: S) {
+        if self.level_attributes.len() <= index {
+            self.level_attributes.resize(index + 1, Vec::new());
+        }
+        let attributes = &mut self.level_attributes[index];
+        let value = value.into();
+        match attributes.iter_mut().find(|(n, _)| n == name) {
+            Some(attribute) => attribute.1 = value,
+            None => attributes.push((name.to_owned(), value)),
+        }
+    }
+}
+
+/// Returns the grid of the levels of a text file, judging by its extension,
+/// or nothing if the file is in the SLC format.
+fn text_grid(path: &Path) -> Option<&'static dyn Grid> {
+    let ext = path.extension()?.to_str()?;
+    if ext.eq_ignore_ascii_case("xsb") || ext.eq_ignore_ascii_case("txt") {
+        Some(&SQUARE)
+    } else if ext.eq_ignore_ascii_case("hsb") {
+        Some(&HEXAGONAL)
+    } else {
+        None
+    }
+}
+
+/// Returns true if the given line is a row of a level in the XSB format.
+///
+/// The letters and digits of coloured boxes and squares, as well as the signs
+/// of special floors, are only accepted in lines with a wall, so that they are
+/// not mistaken for titles.
+fn is_xsb_row(line: &str) -> bool {
+    let is_special = |c: char| matches!(c, 'A'..='I' | 'a'..='i' | '1'..='9' | '^' | 'v' | '<' | '>' | '~' | 'T');
+    !line.trim().is_empty()
+        && line
+            .chars()
+            .all(|c| "#@+$*.-_ ".contains(c) || (is_special(c) && line.contains('#')))
+}
@@ -0,0 +1,189 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use dirs;
+use error::SokobanError;
+use input::KeyBindings;
+use status::StatusField;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml;
+
+/// Represents the user configuration.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The window settings
+    pub window: WindowConfig,
+    /// The gameplay settings
+    pub gameplay: GameplayConfig,
+    /// The theme settings
+    pub theme: ThemeConfig,
+    /// The font settings
+    pub font: FontConfig,
+    /// The status bar settings
+    pub status_bar: StatusBarConfig,
+    /// The names of the keys bound to each action
+    keys: HashMap<String, Vec<String>>,
+}
+
+/// Represents the window settings.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    /// The width of the window in pixels
+    pub width: u32,
+    /// The height of the window in pixels
+    pub height: u32,
+    /// Whether the game starts in fullscreen mode
+    pub fullscreen: bool,
+    /// The minimum width of the tiles, below which big levels are scrolled
+    pub min_tile_size: Option<u32>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            width: 1024,
+            height: 768,
+            fullscreen: false,
+            min_tile_size: None,
+        }
+    }
+}
+
+/// Represents the gameplay settings.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameplayConfig {
+    /// Whether moves are animated
+    pub animation: bool,
+    /// The duration of the animation of a move in milliseconds
+    pub animation_duration: u64,
+    /// The time in milliseconds before a held direction key repeats
+    pub repeat_delay: u64,
+    /// The time in milliseconds between two repetitions of a held direction key
+    pub repeat_interval: u64,
+}
+
+impl Default for GameplayConfig {
+    fn default() -> Self {
+        GameplayConfig {
+            animation: true,
+            animation_duration: 100,
+            repeat_delay: 250,
+            repeat_interval: 100,
+        }
+    }
+}
+
+/// Represents the theme settings.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// The name of the theme or the path to its manifest
+    pub name: String,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            name: String::from("planet-cute"),
+        }
+    }
+}
+
+/// Represents the font settings.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FontConfig {
+    /// The path to the TrueType font file, relative to the assets directory
+    pub path: PathBuf,
+    /// The point size of the font
+    pub size: u16,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        FontConfig {
+            path: PathBuf::from("font/RujisHandwritingFontv.2.0.ttf"),
+            size: 20,
+        }
+    }
+}
+
+/// Represents the status bar settings.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusBarConfig {
+    /// The color of the status bar as RGB components
+    pub color: (u8, u8, u8),
+    /// The color of the text in the status bar as RGB components
+    pub text_color: (u8, u8, u8),
+    /// The fields shown from the left edge of the status bar
+    pub left: Vec<StatusField>,
+    /// The fields shown in the middle of the status bar
+    pub center: Vec<StatusField>,
+    /// The fields shown from the right edge of the status bar
+    pub right: Vec<StatusField>,
+}
+
+impl Default for StatusBarConfig {
+    fn default() -> Self {
+        StatusBarConfig {
+            color: (20, 20, 20),
+            text_color: (255, 192, 0),
+            left: vec![StatusField::Moves, StatusField::Pushes, StatusField::Time],
+            center: vec![StatusField::Boxes, StatusField::Message],
+            right: vec![StatusField::Level, StatusField::Title],
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the given file, or from the default
+    /// location if no file is given.
+    ///
+    /// The default configuration is used when there is no file at the
+    /// default location.
+    pub fn load(path: Option<&Path>) -> Result<Config, SokobanError> {
+        match path {
+            Some(path) => Config::load_file(path),
+            None => match default_path() {
+                Some(ref path) if path.is_file() => Config::load_file(path),
+                _ => Ok(Config::default()),
+            },
+        }
+    }
+
+    /// Loads the configuration from the given file.
+    fn load_file(path: &Path) -> Result<Config, SokobanError> {
+        let contents = fs::read_to_string(path)?;
+        let config = toml::from_str(&contents)?;
+        Ok(config)
+    }
+
+    /// Returns the key bindings.
+    pub fn key_bindings(&self) -> Result<KeyBindings, SokobanError> {
+        let bindings = KeyBindings::new(&self.keys)?;
+        Ok(bindings)
+    }
+}
+
+/// Returns the default location of the configuration file.
+fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("sokoban-rs").join("config.toml"))
+}
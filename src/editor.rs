@@ -0,0 +1,267 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The level editor.
+
+use collection::Collection;
+use error::SokobanError;
+use game::{Direction, Level, Position};
+use grid::Grid;
+use std::cmp;
+use std::path::{Path, PathBuf};
+
+/// Represents what the editor draws on the grid.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tool {
+    /// Draws walls
+    Wall,
+    /// Clears cells down to the floor
+    Floor,
+    /// Draws goal squares
+    Goal,
+    /// Draws boxes
+    Box,
+    /// Moves the player
+    Player,
+}
+
+impl Tool {
+    /// Returns the name of the tool.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Wall => "wall",
+            Tool::Floor => "floor",
+            Tool::Goal => "goal",
+            Tool::Box => "box",
+            Tool::Player => "player",
+        }
+    }
+}
+
+/// Represents the state of the level editor.
+pub struct Editor {
+    /// The file the collection is saved to
+    path: PathBuf,
+    /// The edited collection
+    collection: Collection,
+    /// The grid of the new levels
+    grid: &'static dyn Grid,
+    /// The index of the edited level, which may be one past the end of the
+    /// collection for a level that has never been saved
+    index: usize,
+    /// The edited level
+    level: Level,
+    /// The current tool
+    tool: Tool,
+    /// Whether there are unsaved changes
+    modified: bool,
+    /// Whether the edited level has changed since it was selected
+    level_changed: bool,
+}
+
+impl Editor {
+    /// The number of columns and rows of a new level
+    const NEW_LEVEL_SIZE: (i32, i32) = (10, 8);
+    /// The maximum number of columns and rows of a level
+    const MAX_LEVEL_SIZE: i32 = 100;
+
+    /// Opens a collection file for editing.
+    ///
+    /// A new collection is created if the file does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Editor, SokobanError> {
+        let path = path.as_ref().to_path_buf();
+        let collection = if path.exists() {
+            Collection::load(&path)?
+        } else {
+            let title = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Collection::new(title)
+        };
+        let grid = Collection::grid(&path);
+        let mut editor = Editor {
+            path,
+            collection,
+            grid,
+            index: 0,
+            level: Level::new(0, 0),
+            tool: Tool::Wall,
+            modified: false,
+            level_changed: false,
+        };
+        editor.select(0);
+        Ok(editor)
+    }
+
+    /// Returns the edited level.
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    /// Returns the index of the edited level.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of levels, including the edited one.
+    pub fn level_count(&self) -> usize {
+        cmp::max(self.collection.levels().len(), self.index + 1)
+    }
+
+    /// Returns the title of the collection.
+    pub fn title(&self) -> &str {
+        self.collection.title()
+    }
+
+    /// Returns the current tool.
+    pub fn tool(&self) -> Tool {
+        self.tool
+    }
+
+    /// Changes the current tool.
+    pub fn set_tool(&mut self, tool: Tool) {
+        self.tool = tool;
+    }
+
+    /// Returns true if there are unsaved changes.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Draws on the given cell with the current tool.
+    pub fn draw(&mut self, pos: Position) {
+        let tool = self.tool;
+        self.apply(tool, pos);
+    }
+
+    /// Clears the given cell down to the floor.
+    pub fn erase(&mut self, pos: Position) {
+        self.apply(Tool::Floor, pos);
+    }
+
+    /// Changes the number of columns and rows of the edited level by the given amounts.
+    pub fn resize(&mut self, columns: i32, rows: i32) {
+        let (cols, rs) = self.level.extents();
+        let cols = (cols + columns).clamp(1, Editor::MAX_LEVEL_SIZE);
+        let rs = (rs + rows).clamp(1, Editor::MAX_LEVEL_SIZE);
+        if (cols, rs) != self.level.extents() {
+            self.level.resize(cols, rs);
+            self.modified = true;
+            self.level_changed = true;
+        }
+    }
+
+    /// Starts editing the level at the given index.
+    ///
+    /// Changes made to the current level are kept in memory until the collection is saved.
+    /// Going one past the last level starts a new level.
+    pub fn select(&mut self, index: usize) {
+        self.store();
+        let count = self.collection.levels().len();
+        self.index = cmp::min(index, count);
+        self.level = match self.collection.levels().get(self.index) {
+            Some(level) => level.clone(),
+            None => new_level(self.index + 1, self.grid),
+        };
+        self.level_changed = false;
+    }
+
+    /// Starts editing a new level at the end of the collection.
+    pub fn new_level(&mut self) {
+        let count = self.collection.levels().len();
+        self.select(count);
+    }
+
+    /// Writes the collection to its file.
+    ///
+    /// The edited level must be playable.
+    pub fn save(&mut self) -> Result<&Path, SokobanError> {
+        self.level.check()?;
+        self.store();
+        self.collection.save(&self.path)?;
+        self.modified = false;
+        Ok(&self.path)
+    }
+
+    /// Puts the edited level into the collection if it has been changed.
+    fn store(&mut self) {
+        let count = self.collection.levels().len();
+        if self.index < count {
+            self.collection.replace(self.index, self.level.clone());
+        } else if self.level_changed {
+            self.collection.push(self.level.clone());
+        }
+    }
+
+    /// Draws on the given cell with the given tool.
+    fn apply(&mut self, tool: Tool, pos: Position) {
+        let level = &mut self.level;
+        let is_player = level.is_player(&pos);
+        match tool {
+            Tool::Wall if !is_player => {
+                level.set_box(pos, false);
+                level.set_square(pos, false);
+                level.set_wall(pos, true);
+            }
+            Tool::Floor => {
+                level.set_wall(pos, false);
+                level.set_box(pos, false);
+                level.set_square(pos, false);
+                level.set_floor(pos, None);
+            }
+            Tool::Goal => {
+                level.set_wall(pos, false);
+                level.set_square(pos, true);
+            }
+            Tool::Box if !is_player => {
+                level.set_wall(pos, false);
+                level.set_floor(pos, None);
+                level.set_box(pos, true);
+            }
+            Tool::Player => {
+                level.set_wall(pos, false);
+                level.set_box(pos, false);
+                level.set_floor(pos, None);
+                level.set_player(pos);
+            }
+            _ => return,
+        }
+        self.modified = true;
+        self.level_changed = true;
+    }
+}
+
+/// Creates a new level surrounded by walls.
+fn new_level(number: usize, grid: &'static dyn Grid) -> Level {
+    let (cols, rows) = Editor::NEW_LEVEL_SIZE;
+    let mut level = Level::with_grid(cols, rows, grid);
+    level.set_title(number.to_string());
+    for pos in level.positions() {
+        let is_edge = !level.is_inside(&pos.neighbor(Direction::Left))
+            || !level.is_inside(&pos.neighbor(Direction::Right));
+        if pos.row() == 0 || pos.row() == rows - 1 || is_edge {
+            level.set_wall(pos, true);
+        }
+    }
+    if let Some(pos) = level
+        .positions()
+        .into_iter()
+        .find(|pos| !level.is_wall(pos))
+    {
+        level.set_player(pos);
+    }
+    level
+}
@@ -14,16 +14,29 @@
 // limitations under the License.
 
 use super::game;
+use super::generator;
+use super::input;
+use super::tileset;
 use std::convert::From;
 use std::error;
 use std::fmt::{self, Display, Formatter};
 use std::io;
+use std::path::PathBuf;
+use toml;
+use xml;
 
 /// Represents an application error
 #[derive(Debug)]
 pub enum SokobanError {
     IoError(io::Error),
     ParseError(game::InvalidChar),
+    ConfigError(toml::de::Error),
+    BindingError(input::InvalidBinding),
+    AssetNotFound(PathBuf),
+    TilesetError(tileset::InvalidTileset),
+    LevelError(game::InvalidLevel),
+    XmlError(xml::writer::Error),
+    GeneratorError(generator::GeneratorError),
 }
 
 impl error::Error for SokobanError {
@@ -31,6 +44,13 @@ impl error::Error for SokobanError {
         match *self {
             SokobanError::IoError(..) => "I/O error",
             SokobanError::ParseError(..) => "Level parsing error",
+            SokobanError::ConfigError(..) => "Configuration error",
+            SokobanError::BindingError(..) => "Key binding error",
+            SokobanError::AssetNotFound(..) => "Asset not found",
+            SokobanError::TilesetError(..) => "Invalid tileset",
+            SokobanError::LevelError(..) => "Invalid level",
+            SokobanError::XmlError(..) => "XML writing error",
+            SokobanError::GeneratorError(..) => "Level generation error",
         }
     }
 }
@@ -40,6 +60,15 @@ impl Display for SokobanError {
         match *self {
             SokobanError::IoError(ref err) => write!(f, "{}", *err),
             SokobanError::ParseError(ref err) => write!(f, "{}", *err),
+            SokobanError::ConfigError(ref err) => write!(f, "{}", *err),
+            SokobanError::BindingError(ref err) => write!(f, "{}", *err),
+            SokobanError::AssetNotFound(ref path) => {
+                write!(f, "could not find asset `{}'", path.display())
+            }
+            SokobanError::TilesetError(ref err) => write!(f, "{}", *err),
+            SokobanError::LevelError(ref err) => write!(f, "{}", *err),
+            SokobanError::XmlError(ref err) => write!(f, "{}", *err),
+            SokobanError::GeneratorError(ref err) => write!(f, "{}", *err),
         }
     }
 }
@@ -55,3 +84,39 @@ impl From<game::InvalidChar> for SokobanError {
         SokobanError::ParseError(err)
     }
 }
+
+impl From<toml::de::Error> for SokobanError {
+    fn from(err: toml::de::Error) -> Self {
+        SokobanError::ConfigError(err)
+    }
+}
+
+impl From<input::InvalidBinding> for SokobanError {
+    fn from(err: input::InvalidBinding) -> Self {
+        SokobanError::BindingError(err)
+    }
+}
+
+impl From<tileset::InvalidTileset> for SokobanError {
+    fn from(err: tileset::InvalidTileset) -> Self {
+        SokobanError::TilesetError(err)
+    }
+}
+
+impl From<game::InvalidLevel> for SokobanError {
+    fn from(err: game::InvalidLevel) -> Self {
+        SokobanError::LevelError(err)
+    }
+}
+
+impl From<xml::writer::Error> for SokobanError {
+    fn from(err: xml::writer::Error) -> Self {
+        SokobanError::XmlError(err)
+    }
+}
+
+impl From<generator::GeneratorError> for SokobanError {
+    fn from(err: generator::GeneratorError) -> Self {
+        SokobanError::GeneratorError(err)
+    }
+}
@@ -0,0 +1,91 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Special floors and the rules they add to the game.
+
+use game::{Direction, Level, Position};
+
+/// Represents a floor on which special rules apply.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Floor {
+    /// A floor that the player and the boxes only cross in the given direction
+    OneWay(Direction),
+    /// A floor on which pushed boxes slide until they are blocked
+    Ice,
+    /// A pad that moves the player onto the other pad of its pair
+    Teleport,
+}
+
+/// Describes how special floors change the moves of the player and the boxes.
+///
+/// Every move goes through all the rules of `RULES`, in turn. A new kind of
+/// floor only needs a new rule, which overrides the steps it changes.
+pub trait Rule: Sync {
+    /// Returns true if the player or a box may move from a position in the given direction.
+    fn allows(&self, _level: &Level, _from: &Position, _dir: Direction) -> bool {
+        true
+    }
+
+    /// Returns where a box pushed onto the given position in the given direction comes to rest.
+    fn box_destination(&self, _level: &Level, pos: Position, _dir: Direction) -> Position {
+        pos
+    }
+
+    /// Returns where the player who has stepped onto the given position ends up.
+    fn player_destination(&self, _level: &Level, pos: Position) -> Position {
+        pos
+    }
+}
+
+/// The rule of one-way floors, which can only be entered and left in their direction.
+pub struct OneWayRule;
+
+/// The rule of ice, on which pushed boxes keep sliding.
+pub struct IceRule;
+
+/// The rule of teleport pads, which are paired in the order they are written in.
+pub struct TeleportRule;
+
+/// The rules applied to every move
+pub static RULES: [&dyn Rule; 3] = [&OneWayRule, &IceRule, &TeleportRule];
+
+impl Rule for OneWayRule {
+    fn allows(&self, level: &Level, from: &Position, dir: Direction) -> bool {
+        let crosses = |pos: &Position| match level.floor(pos) {
+            Some(Floor::OneWay(way)) => way == dir,
+            _ => true,
+        };
+        crosses(from) && crosses(&level.grid().neighbor(from, dir))
+    }
+}
+
+impl Rule for IceRule {
+    fn box_destination(&self, level: &Level, pos: Position, dir: Direction) -> Position {
+        let mut pos = pos;
+        while level.floor(&pos) == Some(Floor::Ice) && level.can_move(&pos, dir) {
+            pos = level.grid().neighbor(&pos, dir);
+        }
+        pos
+    }
+}
+
+impl Rule for TeleportRule {
+    fn player_destination(&self, level: &Level, pos: Position) -> Position {
+        match level.teleport_partner(&pos) {
+            Some(partner) if level.is_free(&partner) && !level.is_player(&partner) => partner,
+            _ => pos,
+        }
+    }
+}
@@ -13,12 +13,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::{self, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use std::str::FromStr;
 
+use cells::CellSet;
+use floor::{Floor, RULES};
+use grid::{Grid, SQUARE};
+
 /// Represents a direction.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     /// Up
     Up,
@@ -28,6 +34,84 @@ pub enum Direction {
     Left,
     /// Right
     Right,
+    /// Up and left, on a hexagonal grid
+    UpLeft,
+    /// Up and right, on a hexagonal grid
+    UpRight,
+    /// Down and left, on a hexagonal grid
+    DownLeft,
+    /// Down and right, on a hexagonal grid
+    DownRight,
+}
+
+impl Direction {
+    /// Returns the opposite direction.
+    pub fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+            Direction::UpLeft => Direction::DownRight,
+            Direction::UpRight => Direction::DownLeft,
+            Direction::DownLeft => Direction::UpRight,
+            Direction::DownRight => Direction::UpLeft,
+        }
+    }
+}
+
+/// The number of colours that boxes and squares can have, besides plain ones
+pub const MAX_COLOR: u8 = 9;
+
+/// Represents a move made by the player.
+#[derive(Copy, Clone, Debug)]
+pub struct Move {
+    /// The direction of the move
+    direction: Direction,
+    /// Whether a box was pushed during the move
+    push: bool,
+    /// Whether a box was pulled during the move
+    pull: bool,
+    /// The index of the player who moved
+    player: usize,
+    /// The position of the player before the move
+    origin: Position,
+    /// The positions of the box that was pushed or pulled, before and after the move
+    box_motion: Option<(Position, Position)>,
+}
+
+impl Move {
+    /// Returns the direction of the move.
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns true if a box was pushed during the move.
+    pub fn is_push(&self) -> bool {
+        self.push
+    }
+
+    /// Returns true if a box was pulled during the move, in a level played in reverse.
+    pub fn is_pull(&self) -> bool {
+        self.pull
+    }
+
+    /// Returns the index of the player who moved.
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// Returns the position of the player before the move.
+    pub fn origin(&self) -> Position {
+        self.origin
+    }
+
+    /// Returns the positions of the box that was moved, before and after the move.
+    ///
+    /// A box pushed onto ice may end up several cells away.
+    pub fn box_motion(&self) -> Option<(Position, Position)> {
+        self.box_motion
+    }
 }
 
 /// Represents a position in the world.
@@ -40,13 +124,19 @@ impl Position {
         Position(row, col)
     }
 
-    /// Returns the position that is next to this one in the given direction.
+    /// Returns the position that is next to this one in the given direction, on a square grid.
+    ///
+    /// Use `Grid::neighbor` to follow the directions of the grid of a level.
     pub fn neighbor(&self, dir: Direction) -> Position {
         match dir {
             Direction::Up => Position(self.0 - 1, self.1),
             Direction::Down => Position(self.0 + 1, self.1),
             Direction::Left => Position(self.0, self.1 - 1),
             Direction::Right => Position(self.0, self.1 + 1),
+            Direction::UpLeft => Position(self.0 - 1, self.1 - 1),
+            Direction::UpRight => Position(self.0 - 1, self.1 + 1),
+            Direction::DownLeft => Position(self.0 + 1, self.1 - 1),
+            Direction::DownRight => Position(self.0 + 1, self.1 + 1),
         }
     }
 
@@ -62,37 +152,285 @@ impl Position {
 }
 
 /// Represents the state of the level.
+///
+/// The walls, squares and special floors are shared between clones until one
+/// of them is edited, so that cloning a level mostly copies its boxes and players.
 #[derive(Clone)]
 pub struct Level {
     /// The level's title
     title: String,
-    /// The player's position
-    player: Position,
+    /// The positions of the players
+    players: Vec<Position>,
+    /// The index of the player being moved
+    active: usize,
     /// The current number of steps
     steps: i32,
+    /// The current number of pushes
+    pushes: i32,
+    /// The moves made so far
+    history: Vec<Move>,
+    /// The moves that have been undone and can be redone
+    undone: Vec<Move>,
     /// The positions of the walls
-    walls: HashSet<Position>,
+    walls: Rc<CellSet>,
     /// The positions of the boxes
-    boxes: HashSet<Position>,
+    boxes: CellSet,
     /// The positions of the squares
-    squares: HashSet<Position>,
+    squares: Rc<CellSet>,
+    /// The colours of the boxes that are not plain
+    box_colors: HashMap<Position, u8>,
+    /// The colours of the squares that are not plain
+    square_colors: Rc<HashMap<Position, u8>>,
+    /// The special floors
+    floors: Rc<HashMap<Position, Floor>>,
+    /// The teleport pads, in the order they are written in the level
+    teleports: Rc<Vec<Position>>,
+    /// The Zobrist hash of the boxes and their colours
+    box_hash: u64,
+    /// The top-left cell of the area the player being moved can walk to,
+    /// unless it has to be found again
+    canonical_player: Option<Position>,
     /// The number of columns and rows in the level
     extents: (i32, i32),
+    /// The layout of the cells
+    grid: &'static dyn Grid,
+    /// The player's starting position in the original level, if this one is played in reverse
+    reverse_start: Option<Position>,
 }
 
 impl Level {
+    /// Creates an empty level with the given number of columns and rows.
+    pub fn new(columns: i32, rows: i32) -> Level {
+        Level::with_grid(columns, rows, &SQUARE)
+    }
+
+    /// Creates an empty level laid out on the given grid.
+    pub fn with_grid(columns: i32, rows: i32, grid: &'static dyn Grid) -> Level {
+        Level {
+            title: String::new(),
+            players: vec![Position(0, 0)],
+            active: 0,
+            steps: 0,
+            pushes: 0,
+            history: Vec::new(),
+            undone: Vec::new(),
+            walls: Rc::new(CellSet::new()),
+            boxes: CellSet::new(),
+            squares: Rc::new(CellSet::new()),
+            box_colors: HashMap::new(),
+            square_colors: Rc::new(HashMap::new()),
+            floors: Rc::new(HashMap::new()),
+            teleports: Rc::new(Vec::new()),
+            box_hash: 0,
+            canonical_player: None,
+            extents: (columns, rows),
+            grid,
+            reverse_start: None,
+        }
+    }
+
+    /// Returns a copy of this level to be played in reverse.
+    ///
+    /// The boxes start on the squares and the player pulls them back to their
+    /// starting positions, which become the squares of the reversed level.
+    /// Stepping away from a box leaves it in place, while pulling drags it along.
+    ///
+    /// Pulls do not follow the rules of the special floors, nor move several
+    /// players, so levels with special floors or several players cannot be
+    /// played in reverse.
+    pub fn reversed(&self) -> Option<Level> {
+        if self.has_special_floors() || self.player_count() > 1 {
+            return None;
+        }
+        let mut level = self.clone();
+        level.boxes = (*self.squares).clone();
+        level.squares = Rc::new(self.boxes.clone());
+        level.box_colors = (*self.square_colors).clone();
+        level.square_colors = Rc::new(self.box_colors.clone());
+        level.reverse_start = Some(self.player());
+        level.forget_moves();
+        if level.is_box(&self.player()) {
+            // The player stood on a square: put them on the nearest floor instead
+            if let Some(pos) = level.nearest_free(self.player()) {
+                level.players[level.active] = pos;
+            }
+        }
+        level.rehash();
+        Some(level)
+    }
+
+    /// Returns true if this level is played in reverse.
+    pub fn is_reversed(&self) -> bool {
+        self.reverse_start.is_some()
+    }
+
+    /// Returns the moves that solve the original level, once this level
+    /// has been completed in reverse.
+    ///
+    /// The player first walks to the position where the last pull ended,
+    /// then plays the moves backwards down to the first pull, turning pulls into pushes.
+    /// The walks before the first pull and after the last one are left out.
+    pub fn forward_solution(&self) -> Option<Vec<Direction>> {
+        let start = self.reverse_start?;
+        if !self.is_completed() {
+            return None;
+        }
+        let first = match self.history.iter().position(|mv| mv.is_pull()) {
+            Some(index) => index,
+            None => return Some(Vec::new()),
+        };
+        let last = self.history.iter().rposition(|mv| mv.is_pull())?;
+        let end = self
+            .grid
+            .neighbor(&self.history[last].origin(), self.history[last].direction());
+        let mut solution = self.walk(start, end)?;
+        solution.extend(
+            self.history[first..=last]
+                .iter()
+                .rev()
+                .map(|mv| mv.direction().opposite()),
+        );
+        Some(solution)
+    }
+
     /// Moves the player in the given direction if possible.
-    pub fn step(&mut self, dir: Direction) {
-        let next_to_player = self.player.neighbor(dir);
-        if self.is_free(&next_to_player) {
-            self.move_player(next_to_player);
-        } else if self.is_box(&next_to_player) {
-            let next_to_box = next_to_player.neighbor(dir);
-            if self.is_free(&next_to_box) {
-                self.move_box(&next_to_player, next_to_box);
-                self.move_player(next_to_player);
+    ///
+    /// Returns the move that was made, if any.
+    pub fn step(&mut self, dir: Direction) -> Option<Move> {
+        let mv = self.make_move(dir)?;
+        self.history.push(mv);
+        self.undone.clear();
+        Some(mv)
+    }
+
+    /// Moves the player in the given direction in a level played in reverse,
+    /// pulling the box behind them if there is one.
+    ///
+    /// Returns the move that was made, if any.
+    pub fn pull(&mut self, dir: Direction) -> Option<Move> {
+        if !self.is_reversed() {
+            return None;
+        }
+        let mv = self.make_pull(dir)?;
+        self.history.push(mv);
+        self.undone.clear();
+        Some(mv)
+    }
+
+    /// Cancels the last move.
+    ///
+    /// Returns the move that was cancelled, if any.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.history.pop()?;
+        self.activate(mv.player());
+        if let Some((from, to)) = mv.box_motion() {
+            self.move_box(&to, from);
+            self.pushes -= 1;
+        }
+        self.place_player(mv.origin());
+        self.steps -= 1;
+        self.undone.push(mv);
+        Some(mv)
+    }
+
+    /// Makes the last cancelled move again.
+    ///
+    /// Returns the move that was made, if any.
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.undone.pop()?;
+        self.activate(mv.player());
+        let redone = if mv.is_pull() {
+            self.make_pull(mv.direction())
+        } else {
+            self.make_move(mv.direction())
+        };
+        if let Some(mv) = redone {
+            self.history.push(mv);
+        }
+        redone
+    }
+
+    /// Returns the moves played since the beginning of the level.
+    pub fn moves(&self) -> &[Move] {
+        &self.history
+    }
+
+    /// Moves the player in the given direction without recording the move.
+    ///
+    /// The special floors may stop the move, make the pushed box slide or
+    /// take the player elsewhere. Boxes cannot be pushed in a level played in reverse.
+    fn make_move(&mut self, dir: Direction) -> Option<Move> {
+        let player = self.player();
+        let next_to_player = self.grid.neighbor(&player, dir);
+        if !self.grid.directions().contains(&dir) || !self.allows(&player, dir) {
+            return None;
+        }
+        let mut box_motion = None;
+        if self.is_box(&next_to_player) && !self.is_reversed() {
+            if !self.can_move(&next_to_player, dir) {
+                return None;
             }
+            let rest = RULES
+                .iter()
+                .fold(self.grid.neighbor(&next_to_player, dir), |pos, rule| {
+                    rule.box_destination(self, pos, dir)
+                });
+            if !self.is_inside(&rest) {
+                return None;
+            }
+            self.move_box(&next_to_player, rest);
+            self.pushes += 1;
+            box_motion = Some((next_to_player, rest));
+        } else if !self.is_open(&next_to_player) {
+            return None;
+        }
+        let destination = RULES.iter().fold(next_to_player, |pos, rule| {
+            rule.player_destination(self, pos)
+        });
+        if !self.is_inside(&destination) {
+            if let Some((from, to)) = box_motion {
+                self.move_box(&to, from);
+                self.pushes -= 1;
+            }
+            return None;
         }
+        self.move_player(destination);
+        Some(Move {
+            direction: dir,
+            push: box_motion.is_some(),
+            pull: false,
+            player: self.active,
+            origin: player,
+            box_motion,
+        })
+    }
+
+    /// Moves the player and the box behind them without recording the move.
+    fn make_pull(&mut self, dir: Direction) -> Option<Move> {
+        let player = self.player();
+        let next_to_player = self.grid.neighbor(&player, dir);
+        if !self.grid.directions().contains(&dir) || !self.is_open(&next_to_player) {
+            return None;
+        }
+        let behind_player = self.grid.neighbor(&player, dir.opposite());
+        let pull = self.is_box(&behind_player);
+        if pull {
+            self.move_box(&behind_player, player);
+            self.pushes += 1;
+        }
+        self.move_player(next_to_player);
+        Some(Move {
+            direction: dir,
+            push: false,
+            pull,
+            player: self.active,
+            origin: player,
+            box_motion: if pull {
+                Some((behind_player, player))
+            } else {
+                None
+            },
+        })
     }
 
     /// Returns the current number of steps.
@@ -100,9 +438,35 @@ impl Level {
         self.steps
     }
 
-    /// Returns true if the level is completed.
+    /// Returns the current number of pushes.
+    pub fn get_pushes(&self) -> i32 {
+        self.pushes
+    }
+
+    /// Returns the number of boxes.
+    pub fn box_count(&self) -> usize {
+        self.boxes.len()
+    }
+
+    /// Returns the number of boxes that are on a square.
+    pub fn boxes_on_squares(&self) -> usize {
+        self.boxes
+            .iter()
+            .filter(|pos| self.is_square(pos) && self.box_color(pos) == self.square_color(pos))
+            .count()
+    }
+
+    /// Returns true if the level is completed, with every box on a square of its colour.
+    ///
+    /// A level played in reverse is completed when the boxes are back on their
+    /// starting positions and the player can walk back to theirs.
     pub fn is_completed(&self) -> bool {
-        self.squares.difference(&self.boxes).count() == 0
+        self.squares
+            .iter()
+            .all(|pos| self.boxes.contains(pos) && self.box_color(pos) == self.square_color(pos))
+            && self
+                .reverse_start
+                .is_none_or(|start| self.walk(self.player(), start).is_some())
     }
 
     /// Returns true if the given location is free.
@@ -115,9 +479,131 @@ impl Level {
         self.boxes.contains(pos)
     }
 
-    /// Returns true if the player is at the given position.
+    /// Returns the position of the player being moved.
+    pub fn player(&self) -> Position {
+        self.players[self.active]
+    }
+
+    /// Returns an iterator over the positions of all the players.
+    pub fn players(&self) -> impl Iterator<Item = &Position> {
+        self.players.iter()
+    }
+
+    /// Returns the number of players.
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Returns the index of the player being moved.
+    pub fn active_player(&self) -> usize {
+        self.active
+    }
+
+    /// Chooses the player to be moved.
+    pub fn select_player(&mut self, index: usize) {
+        if index < self.players.len() {
+            self.activate(index);
+        }
+    }
+
+    /// Passes control to the next player, or back to the first one.
+    pub fn switch_player(&mut self) {
+        let next = (self.active + 1) % self.players.len();
+        self.activate(next);
+    }
+
+    /// Returns an iterator over the positions of the boxes.
+    pub fn boxes(&self) -> impl Iterator<Item = &Position> {
+        self.boxes.iter()
+    }
+
+    /// Returns an iterator over the positions of the squares.
+    pub fn squares(&self) -> impl Iterator<Item = &Position> {
+        self.squares.iter()
+    }
+
+    /// Returns true if a player is at the given position.
     pub fn is_player(&self, pos: &Position) -> bool {
-        self.player == *pos
+        self.players.contains(pos)
+    }
+
+    /// Returns the special floor at the given position, if any.
+    pub fn floor(&self, pos: &Position) -> Option<Floor> {
+        self.floors.get(pos).cloned()
+    }
+
+    /// Returns true if some floors of this level are special.
+    pub fn has_special_floors(&self) -> bool {
+        !self.floors.is_empty()
+    }
+
+    /// Returns the top-left cell of the area the player being moved can walk to.
+    ///
+    /// All the positions of the player within this area are the same for the game,
+    /// so this cell stands for all of them. On levels with special floors, where
+    /// the player may not be able to walk back, this is the cell of the player.
+    pub fn canonical_player(&self) -> Position {
+        self.canonical_player
+            .unwrap_or_else(|| self.find_canonical_player())
+    }
+
+    /// Returns a 64-bit hash of the position: the boxes and their colours, the
+    /// canonical position of the player being moved and the other players.
+    ///
+    /// The hash of the boxes is kept up to date as they move, with Zobrist keys.
+    pub fn state_hash(&self) -> u64 {
+        let players = self
+            .players
+            .iter()
+            .enumerate()
+            .fold(0, |hash, (index, pos)| {
+                let pos = if index == self.active {
+                    self.canonical_player()
+                } else {
+                    *pos
+                };
+                hash ^ zobrist_key(&pos, PLAYER_KEY + index as u64)
+            });
+        self.box_hash ^ players
+    }
+
+    /// Returns the pad paired with the teleport pad at the given position, if any.
+    ///
+    /// Pads are paired in the order they are written in the level: the first
+    /// with the second, the third with the fourth, and so on.
+    pub fn teleport_partner(&self, pos: &Position) -> Option<Position> {
+        let index = self.teleports.iter().position(|pad| pad == pos)?;
+        self.teleports.get(index ^ 1).cloned()
+    }
+
+    /// Returns true if the rules of the floors let the player or a box move from a
+    /// position in the given direction, leaving aside what stands in the way.
+    pub fn allows(&self, from: &Position, dir: Direction) -> bool {
+        RULES.iter().all(|rule| rule.allows(self, from, dir))
+    }
+
+    /// Returns true if the player or a box can move from a position to the free
+    /// cell next to it in the given direction.
+    pub fn can_move(&self, from: &Position, dir: Direction) -> bool {
+        self.allows(from, dir) && self.is_open(&self.grid.neighbor(from, dir))
+    }
+
+    /// Returns the cell the player would end up in by walking from a position in
+    /// the given direction, if there is no box or other player in the way.
+    pub fn walk_destination(&self, from: &Position, dir: Direction) -> Option<Position> {
+        let next = self.grid.neighbor(from, dir);
+        if !self.grid.directions().contains(&dir)
+            || !self.is_inside(&next)
+            || !self.is_open(&next)
+            || !self.allows(from, dir)
+        {
+            return None;
+        }
+        Some(
+            RULES
+                .iter()
+                .fold(next, |pos, rule| rule.player_destination(self, pos)),
+        )
     }
 
     /// Returns true if there is a square at the given position.
@@ -125,16 +611,49 @@ impl Level {
         self.squares.contains(pos)
     }
 
+    /// Returns the colour of the box at the given position, from 1 to `MAX_COLOR`,
+    /// or 0 for a plain box.
+    pub fn box_color(&self, pos: &Position) -> u8 {
+        self.box_colors.get(pos).cloned().unwrap_or(0)
+    }
+
+    /// Returns the colour of the square at the given position, or 0 for a plain square.
+    pub fn square_color(&self, pos: &Position) -> u8 {
+        self.square_colors.get(pos).cloned().unwrap_or(0)
+    }
+
     /// returns true if there is a wall at the given position.
     pub fn is_wall(&self, pos: &Position) -> bool {
         self.walls.contains(pos)
     }
 
+    /// Returns true if the given position lies within the extents of this level.
+    pub fn is_inside(&self, pos: &Position) -> bool {
+        is_within(self.grid, self.extents, pos)
+    }
+
     /// Returns the number of columns and rows of this level.
+    ///
+    /// On a hexagonal grid, this is the number of cells that fit in the width
+    /// of the level rather than in a row.
     pub fn extents(&self) -> (i32, i32) {
         self.extents
     }
 
+    /// Returns the positions of the cells within the extents of this level, row by row.
+    pub fn positions(&self) -> Vec<Position> {
+        let (cols, rows) = self.extents;
+        (0..rows)
+            .flat_map(|r| (0..2 * cols).filter_map(move |c| self.grid.text_position(r, c)))
+            .filter(|pos| self.is_inside(pos))
+            .collect()
+    }
+
+    /// Returns the layout of the cells of this level.
+    pub fn grid(&self) -> &'static dyn Grid {
+        self.grid
+    }
+
     /// Returns the title
     pub fn title(&self) -> &str {
         &self.title
@@ -145,18 +664,482 @@ impl Level {
         self.title = title.into();
     }
 
+    /// Adds or removes a wall at the given position.
+    ///
+    /// Like the other methods meant for level editors, this forgets the moves made so far.
+    pub fn set_wall(&mut self, pos: Position, wall: bool) {
+        self.edit(pos, |level| {
+            set_contains(Rc::make_mut(&mut level.walls), pos, wall);
+            if wall {
+                Rc::make_mut(&mut level.floors).remove(&pos);
+                level.pair_teleports();
+            }
+        });
+    }
+
+    /// Adds or removes a plain square at the given position.
+    pub fn set_square(&mut self, pos: Position, square: bool) {
+        self.edit(pos, |level| {
+            set_contains(Rc::make_mut(&mut level.squares), pos, square);
+            Rc::make_mut(&mut level.square_colors).remove(&pos);
+            if square {
+                Rc::make_mut(&mut level.floors).remove(&pos);
+                level.pair_teleports();
+            }
+        });
+    }
+
+    /// Makes the floor at the given position special, or plain.
+    pub fn set_floor(&mut self, pos: Position, floor: Option<Floor>) {
+        self.edit(pos, |level| {
+            match floor {
+                Some(floor) => Rc::make_mut(&mut level.floors).insert(pos, floor),
+                None => Rc::make_mut(&mut level.floors).remove(&pos),
+            };
+            level.pair_teleports();
+        });
+    }
+
+    /// Adds or removes a plain box at the given position.
+    pub fn set_box(&mut self, pos: Position, present: bool) {
+        self.edit(pos, |level| {
+            set_contains(&mut level.boxes, pos, present);
+            level.box_colors.remove(&pos);
+        });
+    }
+
+    /// Puts the player being moved at the given position.
+    pub fn set_player(&mut self, pos: Position) {
+        self.edit(pos, |level| level.players[level.active] = pos);
+    }
+
+    /// Changes the number of columns and rows of this level.
+    ///
+    /// Whatever lies outside of the new extents is removed.
+    pub fn resize(&mut self, columns: i32, rows: i32) {
+        self.extents = (columns, rows);
+        let grid = self.grid;
+        let inside = |pos: &Position| is_within(grid, (columns, rows), pos);
+        Rc::make_mut(&mut self.walls).retain(inside);
+        self.boxes.retain(inside);
+        Rc::make_mut(&mut self.squares).retain(inside);
+        self.box_colors.retain(|pos, _| inside(pos));
+        Rc::make_mut(&mut self.square_colors).retain(|pos, _| inside(pos));
+        Rc::make_mut(&mut self.floors).retain(|pos, _| inside(pos));
+        self.pair_teleports();
+        self.forget_moves();
+        self.rehash();
+    }
+
+    /// Checks that the level can be played.
+    pub fn check(&self) -> Result<(), InvalidLevel> {
+        for (index, player) in self.players.iter().enumerate() {
+            if !self.is_inside(player)
+                || !self.is_free(player)
+                || self.players[..index].contains(player)
+            {
+                return Err(InvalidLevel::MisplacedPlayer);
+            }
+        }
+        if self.boxes.is_empty() {
+            return Err(InvalidLevel::NoBox);
+        }
+        if self.boxes.len() != self.squares.len() {
+            return Err(InvalidLevel::BoxCount(self.boxes.len(), self.squares.len()));
+        }
+        for color in 1..=MAX_COLOR {
+            let boxes = self.box_colors.values().filter(|&&c| c == color).count();
+            let squares = self.square_colors.values().filter(|&&c| c == color).count();
+            if boxes != squares {
+                return Err(InvalidLevel::ColorCount(color, boxes, squares));
+            }
+        }
+        if self.boxes.iter().any(|pos| self.walls.contains(pos)) {
+            return Err(InvalidLevel::BoxInWall);
+        }
+        // Only boxes of the same colour and plain players can be written on a coloured square
+        let box_clash = self
+            .boxes
+            .iter()
+            .any(|pos| self.is_square(pos) && self.box_color(pos) != self.square_color(pos));
+        let player_clash = self.players.iter().any(|pos| self.square_color(pos) != 0);
+        if box_clash || player_clash {
+            return Err(InvalidLevel::ColorClash);
+        }
+        // Special floors cannot be written under the boxes and the players
+        if self
+            .boxes
+            .iter()
+            .chain(self.players.iter())
+            .any(|pos| self.floors.contains_key(pos))
+        {
+            return Err(InvalidLevel::CoveredFloor);
+        }
+        if !self.teleports.len().is_multiple_of(2) {
+            return Err(InvalidLevel::UnpairedTeleport);
+        }
+        Ok(())
+    }
+
+    /// Applies a change made by a level editor within the extents of the level.
+    fn edit<F: FnOnce(&mut Level)>(&mut self, pos: Position, change: F) {
+        if self.is_inside(&pos) {
+            change(self);
+            self.forget_moves();
+            self.rehash();
+        }
+    }
+
+    /// Forgets the moves made so far, which can no longer be undone.
+    pub fn forget_moves(&mut self) {
+        self.steps = 0;
+        self.pushes = 0;
+        self.history.clear();
+        self.undone.clear();
+    }
+
+    /// Returns true if the given location is free and no player stands there.
+    fn is_open(&self, pos: &Position) -> bool {
+        self.is_inside(pos) && self.is_free(pos) && !self.is_player(pos)
+    }
+
+    /// Lists the teleport pads again after the floors have changed.
+    fn pair_teleports(&mut self) {
+        let grid = self.grid;
+        let mut pads: Vec<Position> = self
+            .floors
+            .iter()
+            .filter(|&(_, &floor)| floor == Floor::Teleport)
+            .map(|(&pos, _)| pos)
+            .collect();
+        pads.sort_by_key(|pos| (pos.row(), grid.half_column(pos)));
+        self.teleports = Rc::new(pads);
+    }
+
+    /// Finds the shortest walk from a position to another one, without moving any box.
+    fn walk(&self, from: Position, to: Position) -> Option<Vec<Direction>> {
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(pos) = queue.pop_front() {
+            if pos == to {
+                let mut path = Vec::new();
+                let mut pos = pos;
+                while let Some(&(previous, dir)) = parents.get(&pos) {
+                    path.push(dir);
+                    pos = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &dir in self.grid.directions() {
+                let next = self.grid.neighbor(&pos, dir);
+                if next != from
+                    && self.is_inside(&next)
+                    && self.is_free(&next)
+                    && !parents.contains_key(&next)
+                {
+                    parents.insert(next, (pos, dir));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds the free position closest to the given one, walking over boxes but not walls.
+    fn nearest_free(&self, from: Position) -> Option<Position> {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(from);
+        queue.push_back(from);
+        while let Some(pos) = queue.pop_front() {
+            if self.is_free(&pos) {
+                return Some(pos);
+            }
+            for &dir in self.grid.directions() {
+                let next = self.grid.neighbor(&pos, dir);
+                if self.is_inside(&next) && !self.is_wall(&next) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
     /// moves the player to the given position.
     fn move_player(&mut self, pos: Position) {
-        if pos != self.player {
-            self.player = pos;
+        if pos != self.player() {
+            self.place_player(pos);
             self.steps += 1;
         }
     }
 
+    /// Puts the player being moved at the given position and keeps track of their area.
+    ///
+    /// Walking does not change the area the player can walk to, unless a box has
+    /// moved. On levels with special floors, the exact cell of the player is kept.
+    fn place_player(&mut self, pos: Position) {
+        self.players[self.active] = pos;
+        if self.canonical_player.is_none() || self.has_special_floors() {
+            self.canonical_player = Some(self.find_canonical_player());
+        }
+    }
+
     /// moves a box from a position to another position.
     fn move_box(&mut self, from: &Position, to: Position) {
-        if self.boxes.remove(from) {
-            self.boxes.insert(to);
+        if self.boxes.move_cell(from, to) {
+            let color = self.box_color(from);
+            if color != 0 {
+                self.box_colors.remove(from);
+                self.box_colors.insert(to, color);
+            }
+            self.box_hash ^=
+                zobrist_key(from, u64::from(color)) ^ zobrist_key(&to, u64::from(color));
+            self.canonical_player = None;
+        }
+    }
+
+    /// Passes control to the given player.
+    fn activate(&mut self, index: usize) {
+        if index != self.active {
+            self.active = index;
+            self.canonical_player = None;
+        }
+    }
+
+    /// Computes the hash of the boxes from scratch after the level has been edited,
+    /// and forgets the area of the player until they move.
+    fn rehash(&mut self) {
+        self.box_hash = self.boxes.iter().fold(0, |hash, pos| {
+            hash ^ zobrist_key(pos, u64::from(self.box_color(pos)))
+        });
+        self.canonical_player = None;
+    }
+
+    /// Returns the top-left cell of the area the player being moved can walk to,
+    /// without pushing boxes nor walking through the other players.
+    ///
+    /// One-way floors and teleport pads may lead somewhere without a way back,
+    /// so that the player is not free to move within the area: on levels with
+    /// special floors, the cell of the player is returned instead.
+    fn find_canonical_player(&self) -> Position {
+        let start = self.player();
+        if self.has_special_floors() {
+            return start;
+        }
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(start);
+        queue.push_back(start);
+        while let Some(pos) = queue.pop_front() {
+            for &dir in self.grid.directions() {
+                if let Some(next) = self.walk_destination(&pos, dir) {
+                    if !self.is_player(&next) && visited.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        visited.into_iter().min().unwrap_or(start)
+    }
+}
+
+/// The first Zobrist item of the players, after the colours of the boxes
+const PLAYER_KEY: u64 = 16;
+
+/// Returns the Zobrist key of an item at a position, which is a box of the
+/// given colour or a player from `PLAYER_KEY` on.
+///
+/// The keys are drawn from the position and the item with the SplitMix64 mixer,
+/// so that no table is needed for levels of any size.
+fn zobrist_key(pos: &Position, item: u64) -> u64 {
+    let cell = (u64::from(pos.row() as u32) << 32) | u64::from(pos.column() as u32);
+    let mut z = cell ^ item.wrapping_mul(0xD6E8_FEB8_6659_FD93);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Returns the character standing for an item of the given colour, knowing the
+/// characters for a plain item and for an item of the first colour.
+fn color_char(plain: char, first: char, color: u8) -> char {
+    if color == 0 {
+        plain
+    } else {
+        (first as u8 + color - 1) as char
+    }
+}
+
+/// Returns the colour of an item, knowing the character for an item of the first colour.
+fn color_of(c: char, first: char) -> u8 {
+    c as u8 - first as u8 + 1
+}
+
+/// Returns the character standing for a special floor.
+fn floor_char(floor: Floor) -> char {
+    match floor {
+        Floor::OneWay(Direction::Up) => '^',
+        Floor::OneWay(Direction::Down) => 'v',
+        Floor::OneWay(Direction::Left) => '<',
+        Floor::OneWay(Direction::Right) => '>',
+        // Diagonal one-way floors cannot be written
+        Floor::OneWay(_) => ' ',
+        Floor::Ice => '~',
+        Floor::Teleport => 'T',
+    }
+}
+
+/// Returns the special floor a character stands for.
+fn char_floor(c: char) -> Floor {
+    match c {
+        '^' => Floor::OneWay(Direction::Up),
+        'v' => Floor::OneWay(Direction::Down),
+        '<' => Floor::OneWay(Direction::Left),
+        '>' => Floor::OneWay(Direction::Right),
+        '~' => Floor::Ice,
+        _ => Floor::Teleport,
+    }
+}
+
+/// Returns true if a position lies within the given extents of a grid.
+fn is_within(grid: &dyn Grid, extents: (i32, i32), pos: &Position) -> bool {
+    let (cols, rows) = extents;
+    let half_column = grid.half_column(pos);
+    half_column >= 0 && half_column < 2 * cols - 1 && pos.row() >= 0 && pos.row() < rows
+}
+
+/// Adds or removes a position from a set.
+fn set_contains(set: &mut CellSet, pos: Position, present: bool) {
+    if present {
+        set.insert(pos);
+    } else {
+        set.remove(&pos);
+    }
+}
+
+/// Levels are equal when they are in the same position: the same cells and boxes,
+/// the same players, where the player being moved may stand anywhere in the same
+/// area, and the same player to move. The moves made so far do not matter.
+impl PartialEq for Level {
+    fn eq(&self, other: &Level) -> bool {
+        let others_match = self
+            .players
+            .iter()
+            .zip(&other.players)
+            .enumerate()
+            .all(|(index, (a, b))| index == self.active || a == b);
+        self.box_hash == other.box_hash
+            && self.active == other.active
+            && self.players.len() == other.players.len()
+            && self.canonical_player() == other.canonical_player()
+            && others_match
+            && self.boxes == other.boxes
+            && self.box_colors == other.box_colors
+            && self.walls == other.walls
+            && self.squares == other.squares
+            && self.square_colors == other.square_colors
+            && self.floors == other.floors
+            && self.extents == other.extents
+            && self.grid.directions() == other.grid.directions()
+            && self.reverse_start == other.reverse_start
+    }
+}
+
+impl Eq for Level {}
+
+impl Hash for Level {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.state_hash().hash(state);
+    }
+}
+
+impl Display for Level {
+    /// Writes the level in the XSB format, or in the Hexoban text layout,
+    /// one row per line.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (cols, rows) = self.extents;
+        for r in 0..rows {
+            let mut line = String::new();
+            for c in 0..2 * cols {
+                let pos = match self.grid.text_position(r, c) {
+                    Some(pos) if self.is_inside(&pos) => pos,
+                    _ => {
+                        line.push(' ');
+                        continue;
+                    }
+                };
+                let ch = match (
+                    self.is_square(&pos),
+                    self.is_box(&pos),
+                    self.is_player(&pos),
+                ) {
+                    _ if self.is_wall(&pos) => '#',
+                    (true, true, _) if self.box_color(&pos) == self.square_color(&pos) => {
+                        color_char('*', '1', self.box_color(&pos))
+                    }
+                    // A box on a square of another colour is rejected by `check`
+                    (_, true, _) => color_char('$', 'A', self.box_color(&pos)),
+                    (true, false, true) => '+',
+                    (true, false, false) => color_char('.', 'a', self.square_color(&pos)),
+                    (false, false, true) => '@',
+                    (false, false, false) => match self.floor(&pos) {
+                        Some(floor) => floor_char(floor),
+                        None => ' ',
+                    },
+                };
+                line.push(ch);
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the reason why a level cannot be played.
+#[derive(Debug)]
+pub enum InvalidLevel {
+    /// A player is outside of the level, in a wall, on a box or on another player
+    MisplacedPlayer,
+    /// There is no box
+    NoBox,
+    /// The number of boxes differs from the number of squares
+    BoxCount(usize, usize),
+    /// The number of boxes of a colour differs from the number of squares of this colour
+    ColorCount(u8, usize, usize),
+    /// A box is in a wall
+    BoxInWall,
+    /// A box stands on a square of another colour, or a player on a coloured square
+    ColorClash,
+    /// A box or a player starts on a special floor
+    CoveredFloor,
+    /// A teleport pad has no partner
+    UnpairedTeleport,
+}
+
+impl Display for InvalidLevel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            InvalidLevel::MisplacedPlayer => write!(f, "each player must stand on the floor"),
+            InvalidLevel::NoBox => write!(f, "there must be at least one box"),
+            InvalidLevel::BoxCount(boxes, squares) => {
+                write!(f, "there are {} boxes for {} squares", boxes, squares)
+            }
+            InvalidLevel::ColorCount(color, boxes, squares) => write!(
+                f,
+                "there are {} boxes for {} squares of colour {}",
+                boxes, squares, color
+            ),
+            InvalidLevel::BoxInWall => write!(f, "a box is in a wall"),
+            InvalidLevel::ColorClash => write!(
+                f,
+                "boxes can only stand on squares of their colour, and players on plain squares"
+            ),
+            InvalidLevel::CoveredFloor => {
+                write!(f, "boxes and players cannot start on special floors")
+            }
+            InvalidLevel::UnpairedTeleport => write!(f, "teleport pads must come in pairs"),
         }
     }
 }
@@ -182,69 +1165,466 @@ impl FromStr for Level {
     type Err = InvalidChar;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut level = Level {
-            title: String::new(),
-            player: Position(0, 0),
-            steps: 0,
-            walls: HashSet::new(),
-            boxes: HashSet::new(),
-            squares: HashSet::new(),
-            extents: (0, 0),
+        Level::parse(s, &SQUARE)
+    }
+}
+
+impl Level {
+    /// Reads a level laid out on the given grid, in the XSB format or in the
+    /// Hexoban text layout.
+    pub fn parse(s: &str, grid: &'static dyn Grid) -> Result<Level, InvalidChar> {
+        let mut level = Level::with_grid(0, 0, grid);
+        let mut players = Vec::new();
+
+        // Hexoban rows may start on either column: shift the text to line up with the grid
+        let first = s.lines().enumerate().find_map(|(row, line)| {
+            line.chars()
+                .position(|c| c != ' ')
+                .map(|col| (row as i32, col as i32))
+        });
+        let shift = match first {
+            Some((row, col)) if grid.text_position(row, col).is_none() => 1,
+            _ => 0,
         };
 
-        let (mut row, mut col) = (0, 0);
-        for c in s.chars() {
-            let pos = Position(row, col);
-            match c {
-                '\n' => {
-                    row += 1;
-                    col = -1;
-                }
-                '#' => {
-                    level.walls.insert(pos);
-                }
-                '.' => {
-                    level.squares.insert(pos);
-                }
-                '$' => {
-                    level.boxes.insert(pos);
-                }
-                '@' => {
-                    level.player = pos;
-                }
-                '+' => {
-                    level.player = pos;
-                    level.squares.insert(pos);
-                }
-                '*' => {
-                    level.boxes.insert(pos);
-                    level.squares.insert(pos);
-                }
-                ' ' => {}
-                _ => {
-                    return Err(InvalidChar(c, pos));
+        for (row, line) in s.lines().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                let (row, col) = (row as i32, col as i32);
+                let pos = match grid.text_position(row, col + shift) {
+                    Some(pos) => pos,
+                    // Hexagonal cells are separated by blanks
+                    None if c == ' ' || c == '-' || c == '_' => continue,
+                    None => return Err(InvalidChar(c, Position(row, col))),
+                };
+                match c {
+                    '#' => {
+                        Rc::make_mut(&mut level.walls).insert(pos);
+                    }
+                    '.' => {
+                        Rc::make_mut(&mut level.squares).insert(pos);
+                    }
+                    '$' => {
+                        level.boxes.insert(pos);
+                    }
+                    '@' => {
+                        players.push(pos);
+                    }
+                    '+' => {
+                        players.push(pos);
+                        Rc::make_mut(&mut level.squares).insert(pos);
+                    }
+                    '*' => {
+                        level.boxes.insert(pos);
+                        Rc::make_mut(&mut level.squares).insert(pos);
+                    }
+                    'A'..='I' => {
+                        level.boxes.insert(pos);
+                        level.box_colors.insert(pos, color_of(c, 'A'));
+                    }
+                    'a'..='i' => {
+                        Rc::make_mut(&mut level.squares).insert(pos);
+                        Rc::make_mut(&mut level.square_colors).insert(pos, color_of(c, 'a'));
+                    }
+                    '1'..='9' => {
+                        level.boxes.insert(pos);
+                        Rc::make_mut(&mut level.squares).insert(pos);
+                        level.box_colors.insert(pos, color_of(c, '1'));
+                        Rc::make_mut(&mut level.square_colors).insert(pos, color_of(c, '1'));
+                    }
+                    '^' | 'v' | '<' | '>' | '~' | 'T' => {
+                        let floor = char_floor(c);
+                        match floor {
+                            Floor::OneWay(dir) if !grid.directions().contains(&dir) => {
+                                return Err(InvalidChar(c, Position(row, col)));
+                            }
+                            _ => Rc::make_mut(&mut level.floors).insert(pos, floor),
+                        };
+                    }
+                    ' ' | '-' | '_' => {}
+                    _ => {
+                        return Err(InvalidChar(c, Position(row, col)));
+                    }
                 }
             }
-            col += 1;
         }
 
-        // Calculate the extents of the level
-        let (mut w, mut h) = (level.player.column(), level.player.row());
+        if !players.is_empty() {
+            level.players = players;
+        }
+
+        // Calculate the extents of the level, knowing that a cell spans two half columns
+        let (mut w, mut h) = (0, 0);
         for pos in level
-            .walls
+            .players
             .iter()
+            .chain(level.walls.iter())
             .chain(level.squares.iter())
             .chain(level.boxes.iter())
+            .chain(level.floors.keys())
         {
-            if pos.column() > w {
-                w = pos.column();
-            }
-            if pos.row() > h {
-                h = pos.row();
-            }
+            w = w.max(grid.half_column(pos));
+            h = h.max(pos.row());
         }
-        level.extents = (w + 1, h + 1);
+        level.extents = ((w + 3) / 2, h + 1);
 
+        level.pair_teleports();
+        level.rehash();
         Ok(level)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grid::HEXAGONAL;
+
+    fn level(s: &str) -> Level {
+        s.parse().unwrap()
+    }
+
+    /// Replays moves from the start of a level.
+    fn play(level: &Level, moves: &[Direction]) -> Option<Level> {
+        let mut level = level.clone();
+        for &dir in moves {
+            level.step(dir)?;
+        }
+        Some(level)
+    }
+
+    #[test]
+    fn forward_solution_skips_the_walks_around_the_pulls() {
+        let original = level("#@ $ .#\n");
+        let mut reversed = original.reversed().unwrap();
+        for &dir in &[Direction::Right; 3] {
+            assert!(reversed.step(dir).is_some());
+        }
+        for &dir in &[Direction::Left; 2] {
+            assert!(reversed.pull(dir).unwrap().is_pull());
+        }
+        // Walk back to the start, which the forward solution does not need
+        assert!(reversed.step(Direction::Left).is_some());
+        assert!(reversed.is_completed());
+
+        let solution = reversed.forward_solution().unwrap();
+        assert_eq!(solution.len(), 3);
+        assert!(play(&original, &solution).unwrap().is_completed());
+    }
+
+    #[test]
+    fn reversed_level_swaps_boxes_and_squares() {
+        let reversed = level("#@ $ .#\n").reversed().unwrap();
+        assert!(reversed.is_reversed());
+        assert!(reversed.is_box(&Position::new(0, 5)));
+        assert!(reversed.is_square(&Position::new(0, 3)));
+        assert_eq!(reversed.player(), Position::new(0, 1));
+    }
+
+    #[test]
+    fn pull_drags_the_box_behind_the_player() {
+        let mut reversed = level("#@ $ .#\n").reversed().unwrap();
+        for _ in 0..3 {
+            reversed.step(Direction::Right).unwrap();
+        }
+        // Boxes cannot be pushed in reverse
+        assert!(reversed.step(Direction::Right).is_none());
+        let mv = reversed.pull(Direction::Left).unwrap();
+        assert!(mv.is_pull());
+        assert_eq!(
+            mv.box_motion(),
+            Some((Position::new(0, 5), Position::new(0, 4)))
+        );
+        assert_eq!(reversed.get_pushes(), 1);
+
+        // Stepping away leaves the box in place
+        let mv = reversed.step(Direction::Left).unwrap();
+        assert!(!mv.is_pull());
+        assert!(reversed.is_box(&Position::new(0, 4)));
+
+        reversed.undo();
+        reversed.undo();
+        assert!(reversed.is_box(&Position::new(0, 5)));
+        assert_eq!(reversed.player(), Position::new(0, 4));
+        assert_eq!(reversed.redo().map(|mv| mv.is_pull()), Some(true));
+        assert!(reversed.is_box(&Position::new(0, 4)));
+    }
+
+    #[test]
+    fn players_block_each_other() {
+        let mut level = level("#@@ $.#\n");
+        assert_eq!(level.player_count(), 2);
+        assert!(level.step(Direction::Right).is_none());
+        assert_eq!(
+            level.walk_destination(&Position::new(0, 1), Direction::Right),
+            None
+        );
+
+        level.switch_player();
+        assert_eq!(level.active_player(), 1);
+        assert_eq!(level.step(Direction::Right).unwrap().player(), 1);
+        assert!(level.step(Direction::Right).unwrap().is_push());
+        assert!(level.is_completed());
+
+        // Undoing brings back the player who made the move
+        level.switch_player();
+        level.undo();
+        assert_eq!(level.active_player(), 1);
+        assert_eq!(level.player(), Position::new(0, 3));
+    }
+
+    #[test]
+    fn boxes_cannot_be_pushed_onto_players() {
+        let mut level = level("#@$@.#\n");
+        assert!(level.step(Direction::Right).is_none());
+        assert!(level.is_box(&Position::new(0, 2)));
+    }
+
+    #[test]
+    fn hexagonal_neighbors() {
+        let pos = Position::new(2, 2);
+        let neighbors = [
+            (Direction::Left, (2, 1)),
+            (Direction::Right, (2, 3)),
+            (Direction::UpLeft, (1, 1)),
+            (Direction::UpRight, (1, 2)),
+            (Direction::DownLeft, (3, 2)),
+            (Direction::DownRight, (3, 3)),
+        ];
+        for &(dir, (row, column)) in &neighbors {
+            let next = HEXAGONAL.neighbor(&pos, dir);
+            assert_eq!(next, Position::new(row, column));
+            assert_eq!(HEXAGONAL.neighbor(&next, dir.opposite()), pos);
+        }
+        // The diagonals of the square grid keep their own columns
+        assert_eq!(
+            SQUARE.neighbor(&pos, Direction::UpRight),
+            Position::new(1, 3)
+        );
+        assert_eq!(
+            SQUARE.neighbor(&pos, Direction::DownLeft),
+            Position::new(3, 1)
+        );
+    }
+
+    #[test]
+    fn hexagonal_levels_are_parsed_and_played() {
+        let text = " # # # # #\n# @ $ . #\n # # # # #\n";
+        let mut level = Level::parse(text, &HEXAGONAL).unwrap();
+        assert_eq!(level.player(), Position::new(1, 2));
+        assert!(level.is_box(&Position::new(1, 3)));
+        assert!(level.is_square(&Position::new(1, 4)));
+        assert!(level.is_wall(&Position::new(2, 2)));
+        assert!(Level::parse(&level.to_string(), &HEXAGONAL).unwrap() == level);
+
+        // Up is not a direction of the hexagonal grid
+        assert!(level.step(Direction::Up).is_none());
+        assert!(level.step(Direction::UpLeft).is_none());
+        assert!(level.step(Direction::Right).unwrap().is_push());
+        assert!(level.is_completed());
+    }
+
+    #[test]
+    fn boxes_must_match_the_colour_of_their_square() {
+        let mut wrong = level("#@A b#\n");
+        wrong.step(Direction::Right).unwrap();
+        wrong.step(Direction::Right).unwrap();
+        assert_eq!(wrong.box_color(&Position::new(0, 4)), 1);
+        assert_eq!(wrong.square_color(&Position::new(0, 4)), 2);
+        assert_eq!(wrong.boxes_on_squares(), 0);
+        assert!(!wrong.is_completed());
+
+        let mut right = level("#@A a#\n");
+        right.step(Direction::Right).unwrap();
+        right.step(Direction::Right).unwrap();
+        assert_eq!(right.boxes_on_squares(), 1);
+        assert!(right.is_completed());
+
+        // Plain boxes only go on plain squares
+        let mut plain = level("#@$ a#\n");
+        plain.step(Direction::Right).unwrap();
+        plain.step(Direction::Right).unwrap();
+        assert!(!plain.is_completed());
+    }
+
+    #[test]
+    fn coloured_boxes_keep_their_colour_when_pushed_and_undone() {
+        let mut level = level("#@3 .#\n");
+        level.step(Direction::Right).unwrap();
+        assert_eq!(level.box_color(&Position::new(0, 3)), 3);
+        assert_eq!(level.box_color(&Position::new(0, 2)), 0);
+        level.undo();
+        assert_eq!(level.box_color(&Position::new(0, 2)), 3);
+        assert_eq!(level.square_color(&Position::new(0, 2)), 3);
+    }
+
+    #[test]
+    fn check_rejects_unmatched_colours() {
+        assert!(matches!(
+            level("#@A b#\n").check(),
+            Err(InvalidLevel::ColorCount(1, 1, 0))
+        ));
+        let mut level = level("#@A a#\n");
+        assert!(level.check().is_ok());
+        // Players only stand on plain squares
+        level.set_player(Position::new(0, 4));
+        assert!(matches!(level.check(), Err(InvalidLevel::ColorClash)));
+    }
+
+    #[test]
+    fn one_way_floors_are_crossed_in_their_direction() {
+        let mut crossed = level("#@>  #\n");
+        crossed.step(Direction::Right).unwrap();
+        crossed.step(Direction::Right).unwrap();
+        assert_eq!(crossed.player(), Position::new(0, 3));
+        assert!(crossed.step(Direction::Left).is_none());
+
+        let mut pushed = level("#@$>.#\n");
+        pushed.step(Direction::Right).unwrap();
+        pushed.step(Direction::Right).unwrap();
+        assert!(pushed.is_completed());
+
+        // Neither the player nor a box goes against the way
+        let mut blocked = level("#@$<.#\n");
+        assert!(blocked.step(Direction::Right).is_none());
+        let mut blocked = level("#.$>@#\n");
+        assert!(blocked.step(Direction::Left).is_none());
+    }
+
+    #[test]
+    fn boxes_slide_on_ice() {
+        let mut level = level("#@$~~.#\n");
+        let mv = level.step(Direction::Right).unwrap();
+        assert_eq!(
+            mv.box_motion(),
+            Some((Position::new(0, 2), Position::new(0, 5)))
+        );
+        assert_eq!(level.player(), Position::new(0, 2));
+        assert!(level.is_completed());
+        level.undo();
+        assert!(level.is_box(&Position::new(0, 2)));
+    }
+
+    #[test]
+    fn teleport_pads_move_the_player() {
+        let mut level = level("#@T#T $.#\n");
+        assert!(level.check().is_ok());
+        assert_eq!(
+            level.teleport_partner(&Position::new(0, 2)),
+            Some(Position::new(0, 4))
+        );
+        level.step(Direction::Right).unwrap();
+        assert_eq!(level.player(), Position::new(0, 4));
+        level.step(Direction::Right).unwrap();
+        level.step(Direction::Right).unwrap();
+        assert!(level.is_completed());
+        level.undo();
+        level.undo();
+        level.undo();
+        assert_eq!(level.player(), Position::new(0, 1));
+    }
+
+    #[test]
+    fn teleport_pads_are_paired_again_after_edits() {
+        let mut level = level("#@T $.#\n");
+        assert!(matches!(level.check(), Err(InvalidLevel::UnpairedTeleport)));
+        level.set_floor(Position::new(0, 3), Some(Floor::Teleport));
+        assert!(level.check().is_ok());
+        assert_eq!(
+            level.teleport_partner(&Position::new(0, 2)),
+            Some(Position::new(0, 3))
+        );
+        level.set_wall(Position::new(0, 3), true);
+        assert_eq!(level.teleport_partner(&Position::new(0, 2)), None);
+    }
+
+    #[test]
+    fn moves_stay_within_the_level() {
+        // Nothing stops the box at the open edge but the end of the level
+        let mut open = level("@$~~\n");
+        let mv = open.step(Direction::Right).unwrap();
+        assert_eq!(
+            mv.box_motion(),
+            Some((Position::new(0, 1), Position::new(0, 3)))
+        );
+        assert!(open.step(Direction::Right).is_some());
+        assert!(open.step(Direction::Right).is_none());
+        assert!(open.step(Direction::Up).is_none());
+
+        let mut edge = level("@ \n");
+        assert!(edge.step(Direction::Left).is_none());
+    }
+
+    /// Returns the value given to a level by the standard hasher.
+    fn std_hash(level: &Level) -> u64 {
+        use std::collections::hash_map::DefaultHasher;
+        let mut hasher = DefaultHasher::new();
+        level.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn state_hash_is_kept_up_to_date_by_pushes() {
+        let mut level = level("#######\n#@$ . #\n# A  a#\n#######\n");
+        let moves = [
+            Direction::Right,
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+        ];
+        for &dir in &moves {
+            level.step(dir).unwrap();
+            let mut fresh = level.clone();
+            fresh.rehash();
+            assert_eq!(level.state_hash(), fresh.state_hash());
+        }
+        level.undo();
+        level.undo();
+        let mut fresh = level.clone();
+        fresh.rehash();
+        assert_eq!(level.state_hash(), fresh.state_hash());
+    }
+
+    #[test]
+    fn state_hash_tells_colours_apart() {
+        let plain = level("#@$ .#\n");
+        let coloured = level("#@A a#\n");
+        assert_ne!(plain.state_hash(), coloured.state_hash());
+    }
+
+    #[test]
+    fn players_in_the_same_area_give_the_same_key() {
+        let start = level("######\n#@   #\n# $ .#\n######\n");
+        let mut walked = start.clone();
+        walked.step(Direction::Right).unwrap();
+        walked.step(Direction::Right).unwrap();
+        assert_ne!(start.player(), walked.player());
+        assert_eq!(start.canonical_player(), walked.canonical_player());
+        assert_eq!(start.state_hash(), walked.state_hash());
+        assert_eq!(std_hash(&start), std_hash(&walked));
+        assert!(start == walked);
+
+        // Pushing the box moves it, which changes the key
+        let mut pushed = walked.clone();
+        for &dir in &[Direction::Left, Direction::Left, Direction::Down] {
+            pushed.step(dir).unwrap();
+        }
+        assert!(pushed == walked);
+        assert!(pushed.step(Direction::Right).unwrap().is_push());
+        assert!(pushed != walked);
+    }
+
+    #[test]
+    fn boxes_split_the_areas_of_the_player() {
+        let left = level("#@$ .#\n");
+        let mut right = left.clone();
+        right.set_player(Position::new(0, 3));
+        assert_ne!(left.canonical_player(), right.canonical_player());
+        assert_ne!(left.state_hash(), right.state_hash());
+        assert!(left != right);
+    }
+
+    #[test]
+    fn pull_is_only_allowed_in_reverse() {
+        let mut level = level("#@$ .#\n");
+        assert!(level.pull(Direction::Left).is_none());
+    }
+}
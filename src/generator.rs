@@ -0,0 +1,331 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generates new levels.
+//!
+//! A room is first carved out of a rectangle of walls, then the boxes are put
+//! on the goals and pulled away from them by playing the game backwards.
+//! Since every pull can be undone by a push, the resulting level is solvable,
+//! and the solution is checked by replaying it before the level is returned.
+
+use error::SokobanError;
+use game::{Direction, Level, Position};
+use solver::{find_path, reachable_positions};
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+/// Represents the parameters of the levels to generate.
+#[derive(Copy, Clone, Debug)]
+pub struct Settings {
+    /// The number of columns, including the outer walls
+    pub width: i32,
+    /// The number of rows, including the outer walls
+    pub height: i32,
+    /// The number of boxes
+    pub boxes: usize,
+}
+
+/// Represents a generated level along with its solution.
+pub struct Puzzle {
+    /// The level
+    pub level: Level,
+    /// The moves that solve the level
+    pub solution: Vec<Direction>,
+}
+
+impl Puzzle {
+    /// Returns the solution in the LURD notation, with the pushes in capitals.
+    pub fn lurd(&self) -> String {
+        let mut level = self.level.clone();
+        let mut lurd = String::new();
+        for &dir in &self.solution {
+            let letter = match dir {
+                Direction::Left => 'l',
+                Direction::Up => 'u',
+                Direction::Right => 'r',
+                Direction::Down => 'd',
+                _ => unreachable!("Generated levels are laid out on a square grid"),
+            };
+            match level.step(dir) {
+                Some(mv) if mv.is_push() => lurd.push(letter.to_ascii_uppercase()),
+                _ => lurd.push(letter),
+            }
+        }
+        lurd
+    }
+}
+
+/// Represents an error preventing levels from being generated.
+#[derive(Debug)]
+pub enum GeneratorError {
+    /// The level is too small to hold a room
+    TooSmall,
+    /// There are too many boxes for the size of the level
+    TooManyBoxes,
+    /// No level could be found after many attempts
+    NoLevelFound,
+}
+
+impl Display for GeneratorError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            GeneratorError::TooSmall => write!(f, "levels must be at least 5 x 5"),
+            GeneratorError::TooManyBoxes => write!(f, "too many boxes for the size of the level"),
+            GeneratorError::NoLevelFound => write!(f, "could not generate a level"),
+        }
+    }
+}
+
+/// Generates levels from a seed.
+pub struct Generator {
+    /// The parameters of the levels
+    settings: Settings,
+    /// The source of randomness
+    rng: Rng,
+}
+
+impl Generator {
+    /// The number of attempts at generating a level before giving up
+    const MAX_ATTEMPTS: usize = 1000;
+    /// The number of pulls tried per box when playing backwards
+    const PULLS_PER_BOX: usize = 100;
+    /// The minimum average distance between the boxes and the goals
+    const MIN_SCATTER: usize = 3;
+
+    /// Creates a new generator.
+    ///
+    /// The same seed always gives the same levels.
+    pub fn new(settings: Settings, seed: u64) -> Result<Generator, SokobanError> {
+        if settings.width < 5 || settings.height < 5 {
+            return Err(GeneratorError::TooSmall.into());
+        }
+        let inner_area = ((settings.width - 2) * (settings.height - 2)) as usize;
+        if settings.boxes == 0 || settings.boxes * 4 > inner_area {
+            return Err(GeneratorError::TooManyBoxes.into());
+        }
+        Ok(Generator {
+            settings,
+            rng: Rng::new(seed),
+        })
+    }
+
+    /// Generates a new level.
+    pub fn generate(&mut self) -> Result<Puzzle, SokobanError> {
+        for _ in 0..Generator::MAX_ATTEMPTS {
+            let room = self.carve_room();
+            if let Some(puzzle) = self.scatter_boxes(room) {
+                return Ok(puzzle);
+            }
+        }
+        Err(GeneratorError::NoLevelFound.into())
+    }
+
+    /// Returns a level made of a single room surrounded by walls, with goals
+    /// and the player but no boxes.
+    fn carve_room(&mut self) -> Level {
+        let Settings { width, height, .. } = self.settings;
+        let mut level = Level::new(width, height);
+        let mut floor = Vec::new();
+        for r in 0..height {
+            for c in 0..width {
+                let pos = Position::new(r, c);
+                if r == 0 || c == 0 || r == height - 1 || c == width - 1 {
+                    level.set_wall(pos, true);
+                } else {
+                    floor.push(pos);
+                }
+            }
+        }
+
+        // Add walls inside the room as long as the floor stays in one piece
+        let target = floor.len() * (20 + self.rng.below(15)) / 100;
+        let mut walls = 0;
+        for _ in 0..target * 4 {
+            if walls == target {
+                break;
+            }
+            let index = self.rng.below(floor.len());
+            let pos = floor.swap_remove(index);
+            level.set_wall(pos, true);
+            if is_connected(&level, &floor) {
+                walls += 1;
+            } else {
+                level.set_wall(pos, false);
+                floor.push(pos);
+            }
+        }
+
+        // Put the goals and the player on the floor
+        for _ in 0..=self.settings.boxes {
+            let index = self.rng.below(floor.len());
+            let pos = floor.swap_remove(index);
+            if level.squares().count() < self.settings.boxes {
+                level.set_square(pos, true);
+            } else {
+                level.set_player(pos);
+            }
+        }
+        level
+    }
+
+    /// Puts the boxes on the goals and pulls them around, keeping the most
+    /// scattered arrangement.
+    fn scatter_boxes(&mut self, mut level: Level) -> Option<Puzzle> {
+        let goals: Vec<Position> = level.squares().cloned().collect();
+        for goal in &goals {
+            level.set_box(*goal, true);
+        }
+
+        // Each pull is recorded as the position of the player after the pull
+        // and the direction of the push that undoes it.
+        let mut pulls: Vec<(Position, Direction)> = Vec::new();
+        let mut best: Option<(i32, usize, Level)> = None;
+        for _ in 0..self.settings.boxes * Generator::PULLS_PER_BOX {
+            let candidates = find_pulls(&level);
+            if candidates.is_empty() {
+                break;
+            }
+            let (stand, dir) = candidates[self.rng.below(candidates.len())];
+            let behind = level.grid().neighbor(&stand, dir.opposite());
+            level.set_box(level.grid().neighbor(&stand, dir), false);
+            level.set_box(stand, true);
+            level.set_player(behind);
+            pulls.push((behind, dir));
+
+            let score = scatter(&level, &goals);
+            if best.as_ref().is_none_or(|&(s, _, _)| score > s) {
+                best = Some((score, pulls.len(), level.clone()));
+            }
+        }
+
+        let (score, count, mut level) = best?;
+        if (score as usize) < self.settings.boxes * Generator::MIN_SCATTER {
+            return None;
+        }
+        let solution = solve_backwards(&level, &pulls[..count])?;
+
+        // Make sure the solution works
+        let start = level.clone();
+        for &dir in &solution {
+            level.step(dir)?;
+        }
+        if level.is_completed() {
+            Some(Puzzle {
+                level: start,
+                solution,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the pulls that can be made, as the position the player must reach
+/// and the direction of the box from there.
+fn find_pulls(level: &Level) -> Vec<(Position, Direction)> {
+    let reachable = reachable_positions(level);
+    let mut pulls = Vec::new();
+    let mut positions: Vec<&Position> = reachable.keys().collect();
+    positions.sort();
+    for &stand in positions {
+        for &dir in level.grid().directions() {
+            let behind = level.grid().neighbor(&stand, dir.opposite());
+            if level.is_box(&level.grid().neighbor(&stand, dir)) && level.is_free(&behind) {
+                pulls.push((stand, dir));
+            }
+        }
+    }
+    pulls
+}
+
+/// Returns the moves that undo the given pulls, starting from the arrangement
+/// reached after them.
+fn solve_backwards(level: &Level, pulls: &[(Position, Direction)]) -> Option<Vec<Direction>> {
+    let mut level = level.clone();
+    let mut solution = Vec::new();
+    for &(stand, dir) in pulls.iter().rev() {
+        solution.extend(find_path(&level, stand)?);
+        level.set_player(stand);
+        let target = level.grid().neighbor(&stand, dir);
+        level.set_box(target, false);
+        level.set_box(level.grid().neighbor(&target, dir), true);
+        level.set_player(target);
+        solution.push(dir);
+    }
+    Some(solution)
+}
+
+/// Returns true if the given floor positions are all connected.
+fn is_connected(level: &Level, floor: &[Position]) -> bool {
+    let start = match floor.first() {
+        Some(pos) => *pos,
+        None => return true,
+    };
+    let mut reached = HashSet::new();
+    let mut queue = VecDeque::new();
+    reached.insert(start);
+    queue.push_back(start);
+    while let Some(pos) = queue.pop_front() {
+        for &dir in level.grid().directions() {
+            let next = level.grid().neighbor(&pos, dir);
+            if !level.is_wall(&next) && reached.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    reached.len() == floor.len()
+}
+
+/// Returns how far the boxes are from the goals.
+fn scatter(level: &Level, goals: &[Position]) -> i32 {
+    level
+        .boxes()
+        .map(|b| {
+            goals
+                .iter()
+                .map(|g| (b.row() - g.row()).abs() + (b.column() - g.column()).abs())
+                .min()
+                .unwrap_or(0)
+        })
+        .sum()
+}
+
+/// A small pseudo-random number generator (xorshift64*), so that a seed
+/// gives the same levels on every platform and with every version of the
+/// dependencies.
+struct Rng(u64);
+
+impl Rng {
+    /// Creates a new generator from a seed.
+    fn new(seed: u64) -> Rng {
+        // The state must never be zero
+        Rng((seed ^ 0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    /// Returns the next pseudo-random number.
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Returns a pseudo-random number below the given bound.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
@@ -0,0 +1,132 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use game::{Direction, Position};
+
+/// Describes how the cells of a level are laid out.
+///
+/// Positions are given as a row and a column. Neighboring cells are found with
+/// `Grid::neighbor`, but only the directions of the grid lead to them.
+pub trait Grid: Sync {
+    /// Returns the directions in which the player can move.
+    fn directions(&self) -> &'static [Direction];
+
+    /// Returns the cell next to the given one in the given direction.
+    fn neighbor(&self, pos: &Position, dir: Direction) -> Position;
+
+    /// Returns the horizontal position of a cell, in halves of the width of a cell.
+    fn half_column(&self, pos: &Position) -> i32;
+
+    /// Returns the cell that covers the given row and half column.
+    fn position(&self, row: i32, half_column: i32) -> Position;
+
+    /// Returns the cell written at the given row and column of a text, if any.
+    fn text_position(&self, row: i32, column: i32) -> Option<Position>;
+
+    /// Returns the column at which a cell is written in a text.
+    fn text_column(&self, pos: &Position) -> i32;
+}
+
+/// The square grid of the classic game.
+pub struct SquareGrid;
+
+/// The grid of hexagonal cells of Hexoban.
+///
+/// Every other row is shifted by half a cell. In the text layout, the cells
+/// are separated by spaces and the rows alternate between odd and even columns.
+/// A cell written at row `r` and column `c` is stored at column `(r + c) / 2`,
+/// so that the cell up and to the right of another one shares its column.
+pub struct HexGrid;
+
+/// The square grid
+pub static SQUARE: SquareGrid = SquareGrid;
+
+/// The hexagonal grid
+pub static HEXAGONAL: HexGrid = HexGrid;
+
+impl Grid for SquareGrid {
+    fn directions(&self) -> &'static [Direction] {
+        &[
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ]
+    }
+
+    fn neighbor(&self, pos: &Position, dir: Direction) -> Position {
+        pos.neighbor(dir)
+    }
+
+    fn half_column(&self, pos: &Position) -> i32 {
+        2 * pos.column()
+    }
+
+    fn position(&self, row: i32, half_column: i32) -> Position {
+        Position::new(row, half_column.div_euclid(2))
+    }
+
+    fn text_position(&self, row: i32, column: i32) -> Option<Position> {
+        Some(Position::new(row, column))
+    }
+
+    fn text_column(&self, pos: &Position) -> i32 {
+        pos.column()
+    }
+}
+
+impl Grid for HexGrid {
+    fn directions(&self) -> &'static [Direction] {
+        &[
+            Direction::Left,
+            Direction::Right,
+            Direction::UpLeft,
+            Direction::UpRight,
+            Direction::DownLeft,
+            Direction::DownRight,
+        ]
+    }
+
+    fn neighbor(&self, pos: &Position, dir: Direction) -> Position {
+        // The cells up and to the right, or down and to the left, share the column
+        match dir {
+            Direction::UpRight => Position::new(pos.row() - 1, pos.column()),
+            Direction::DownLeft => Position::new(pos.row() + 1, pos.column()),
+            _ => pos.neighbor(dir),
+        }
+    }
+
+    fn half_column(&self, pos: &Position) -> i32 {
+        2 * pos.column() - pos.row()
+    }
+
+    fn position(&self, row: i32, half_column: i32) -> Position {
+        // A cell covers two half columns, starting from one of the same parity as its row
+        let start = half_column - (row + half_column).rem_euclid(2);
+        Position::new(row, (row + start) / 2)
+    }
+
+    fn text_position(&self, row: i32, column: i32) -> Option<Position> {
+        if (row + column).rem_euclid(2) == 0 {
+            Some(Position::new(row, (row + column) / 2))
+        } else {
+            None
+        }
+    }
+
+    fn text_column(&self, pos: &Position) -> i32 {
+        self.half_column(pos)
+    }
+}
@@ -0,0 +1,367 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use game::Direction;
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Represents an action that can be bound to keys.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Move up
+    Up,
+    /// Move down
+    Down,
+    /// Move left
+    Left,
+    /// Move right
+    Right,
+    /// Move up and left, on a hexagonal grid
+    UpLeft,
+    /// Move up and right, on a hexagonal grid
+    UpRight,
+    /// Move down and left, on a hexagonal grid
+    DownLeft,
+    /// Move down and right, on a hexagonal grid
+    DownRight,
+    /// Cancel the last move
+    Undo,
+    /// Make the last cancelled move again
+    Redo,
+    /// Restart the current level
+    Restart,
+    /// Go to the next level
+    NextLevel,
+    /// Go to the previous level
+    PreviousLevel,
+    /// Leave the level-completed screen for the next level
+    Continue,
+    /// Play back the solution of a completed level
+    Replay,
+    /// Switch between playing the level forward and in reverse
+    Reverse,
+    /// Pass control to the next player in levels with several players
+    SwitchPlayer,
+    /// Switch between editing a level and test-playing it, in the level editor
+    TestPlay,
+    /// Switch between fullscreen and windowed mode
+    ToggleFullscreen,
+    /// Zoom in
+    ZoomIn,
+    /// Zoom out
+    ZoomOut,
+    /// Fit the level onto the screen
+    ZoomReset,
+    /// Scroll the view up
+    PanUp,
+    /// Scroll the view down
+    PanDown,
+    /// Scroll the view left
+    PanLeft,
+    /// Scroll the view right
+    PanRight,
+    /// Quit the game
+    Quit,
+}
+
+impl Action {
+    /// Returns the direction of a move action.
+    pub fn direction(self) -> Option<Direction> {
+        match self {
+            Action::Up => Some(Direction::Up),
+            Action::Down => Some(Direction::Down),
+            Action::Left => Some(Direction::Left),
+            Action::Right => Some(Direction::Right),
+            Action::UpLeft => Some(Direction::UpLeft),
+            Action::UpRight => Some(Direction::UpRight),
+            Action::DownLeft => Some(Direction::DownLeft),
+            Action::DownRight => Some(Direction::DownRight),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the action in the configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::UpLeft => "up_left",
+            Action::UpRight => "up_right",
+            Action::DownLeft => "down_left",
+            Action::DownRight => "down_right",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Restart => "restart",
+            Action::NextLevel => "next_level",
+            Action::PreviousLevel => "previous_level",
+            Action::Continue => "continue",
+            Action::Replay => "replay",
+            Action::Reverse => "reverse",
+            Action::SwitchPlayer => "switch_player",
+            Action::TestPlay => "test_play",
+            Action::ToggleFullscreen => "toggle_fullscreen",
+            Action::ZoomIn => "zoom_in",
+            Action::ZoomOut => "zoom_out",
+            Action::ZoomReset => "zoom_reset",
+            Action::PanUp => "pan_up",
+            Action::PanDown => "pan_down",
+            Action::PanLeft => "pan_left",
+            Action::PanRight => "pan_right",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// Returns the names of the keys bound to this action by default.
+    fn default_keys(self) -> &'static [&'static str] {
+        match self {
+            Action::Up => &["Up", "W", "K"],
+            Action::Down => &["Down", "S", "J"],
+            Action::Left => &["Left", "A", "H"],
+            Action::Right => &["Right", "D", "L"],
+            Action::UpLeft => &["Keypad 7"],
+            Action::UpRight => &["Keypad 9"],
+            Action::DownLeft => &["Keypad 1"],
+            Action::DownRight => &["Keypad 3"],
+            Action::Undo => &["U", "Z", "Backspace"],
+            Action::Redo => &["Y"],
+            Action::Restart => &["R"],
+            Action::NextLevel => &["N", "PageDown"],
+            Action::PreviousLevel => &["P", "PageUp"],
+            Action::Continue => &["Return", "Space", "Keypad Enter"],
+            Action::Replay => &["V"],
+            Action::Reverse => &["B"],
+            Action::SwitchPlayer => &["Tab", "X"],
+            Action::TestPlay => &["T"],
+            Action::ToggleFullscreen => &["F11"],
+            Action::ZoomIn => &["=", "+", "Keypad +"],
+            Action::ZoomOut => &["-", "Keypad -"],
+            Action::ZoomReset => &["0", "Keypad 0"],
+            Action::PanUp => &["Keypad 8"],
+            Action::PanDown => &["Keypad 2"],
+            Action::PanLeft => &["Keypad 4"],
+            Action::PanRight => &["Keypad 6"],
+            Action::Quit => &["Escape"],
+        }
+    }
+}
+
+/// The list of all the actions
+const ACTIONS: [Action; 27] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::UpLeft,
+    Action::UpRight,
+    Action::DownLeft,
+    Action::DownRight,
+    Action::Undo,
+    Action::Redo,
+    Action::Restart,
+    Action::NextLevel,
+    Action::PreviousLevel,
+    Action::Continue,
+    Action::Replay,
+    Action::Reverse,
+    Action::SwitchPlayer,
+    Action::TestPlay,
+    Action::ToggleFullscreen,
+    Action::ZoomIn,
+    Action::ZoomOut,
+    Action::ZoomReset,
+    Action::PanUp,
+    Action::PanDown,
+    Action::PanLeft,
+    Action::PanRight,
+    Action::Quit,
+];
+
+impl FromStr for Action {
+    type Err = InvalidBinding;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ACTIONS
+            .iter()
+            .find(|action| action.name() == s)
+            .cloned()
+            .ok_or_else(|| InvalidBinding::UnknownAction(s.to_owned()))
+    }
+}
+
+/// Represents an error due to an invalid key binding.
+#[derive(Debug)]
+pub enum InvalidBinding {
+    /// The action name is unknown
+    UnknownAction(String),
+    /// The key name is unknown
+    UnknownKey(String),
+    /// The key is bound to two different actions
+    DuplicateKey(String, Action, Action),
+}
+
+impl Display for InvalidBinding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            InvalidBinding::UnknownAction(ref name) => write!(f, "unknown action `{}'", name),
+            InvalidBinding::UnknownKey(ref name) => write!(f, "unknown key `{}'", name),
+            InvalidBinding::DuplicateKey(ref name, first, second) => write!(
+                f,
+                "key `{}' is bound to both `{}' and `{}'",
+                name,
+                first.name(),
+                second.name()
+            ),
+        }
+    }
+}
+
+/// Maps keys to actions.
+pub struct KeyBindings {
+    /// The action bound to each key
+    actions: HashMap<Keycode, Action>,
+    /// The name of the first key bound to each action
+    key_names: HashMap<Action, String>,
+}
+
+impl KeyBindings {
+    /// Creates key bindings from the names of the keys bound to each action.
+    ///
+    /// Actions that are not listed keep the default keys that are not bound
+    /// to a listed action.
+    pub fn new(keys: &HashMap<String, Vec<String>>) -> Result<KeyBindings, InvalidBinding> {
+        if let Some(name) = keys.keys().find(|name| name.parse::<Action>().is_err()) {
+            return Err(InvalidBinding::UnknownAction(name.clone()));
+        }
+
+        let mut actions = HashMap::new();
+        let mut key_names = HashMap::new();
+        for &action in ACTIONS.iter() {
+            let names = match keys.get(action.name()) {
+                Some(names) => names,
+                None => continue,
+            };
+            for name in names {
+                let keycode = Keycode::from_name(name)
+                    .ok_or_else(|| InvalidBinding::UnknownKey(name.to_owned()))?;
+                if let Some(other) = actions.insert(keycode, action) {
+                    return Err(InvalidBinding::DuplicateKey(name.to_owned(), other, action));
+                }
+                key_names.entry(action).or_insert_with(|| keycode.name());
+            }
+        }
+
+        // The default keys go to the actions that are not listed, unless they are taken
+        for &action in ACTIONS.iter() {
+            if keys.contains_key(action.name()) {
+                continue;
+            }
+            for &name in action.default_keys() {
+                let keycode = Keycode::from_name(name)
+                    .ok_or_else(|| InvalidBinding::UnknownKey(name.to_owned()))?;
+                if actions.contains_key(&keycode) {
+                    continue;
+                }
+                actions.insert(keycode, action);
+                key_names.entry(action).or_insert_with(|| keycode.name());
+            }
+        }
+        Ok(KeyBindings { actions, key_names })
+    }
+
+    /// Returns the action bound to the given key, if any.
+    pub fn action(&self, keycode: Keycode) -> Option<Action> {
+        self.actions.get(&keycode).cloned()
+    }
+
+    /// Returns the name of a key bound to the given action, if any.
+    pub fn key_name(&self, action: Action) -> Option<&str> {
+        self.key_names.get(&action).map(|name| name.as_str())
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::new(&HashMap::new()).expect("Invalid default key bindings")
+    }
+}
+
+/// Keeps track of the direction keys being held down and repeats
+/// the corresponding moves at a steady pace.
+pub struct KeyRepeat {
+    /// The time before the first repetition
+    delay: Duration,
+    /// The time between two repetitions
+    interval: Duration,
+    /// The directions being held, the most recent one last
+    held: Vec<Direction>,
+    /// The time of the next repetition
+    next: Option<Instant>,
+}
+
+impl KeyRepeat {
+    /// Creates a new instance.
+    pub fn new(delay: Duration, interval: Duration) -> KeyRepeat {
+        KeyRepeat {
+            delay,
+            interval,
+            held: Vec::new(),
+            next: None,
+        }
+    }
+
+    /// Records that the key for the given direction has been pressed.
+    pub fn press(&mut self, dir: Direction) {
+        self.held.retain(|&d| d != dir);
+        self.held.push(dir);
+        self.next = Some(Instant::now() + self.delay);
+    }
+
+    /// Records that the key for the given direction has been released.
+    pub fn release(&mut self, dir: Direction) {
+        self.held.retain(|&d| d != dir);
+        if self.held.is_empty() {
+            self.next = None;
+        }
+    }
+
+    /// Forgets about all the keys being held.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.next = None;
+    }
+
+    /// Returns true if a key is being held.
+    pub fn is_active(&self) -> bool {
+        !self.held.is_empty()
+    }
+
+    /// Returns the direction to move to if a repetition is due.
+    pub fn poll(&mut self) -> Option<Direction> {
+        let dir = *self.held.last()?;
+        let next = self.next?;
+        let now = Instant::now();
+        if now >= next {
+            self.next = Some(now + self.interval);
+            Some(dir)
+        } else {
+            None
+        }
+    }
+}
@@ -19,128 +19,256 @@
 extern crate bitflags;
 #[macro_use]
 extern crate clap;
+extern crate dirs;
 extern crate sdl2;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 extern crate xml;
 
-use clap::App;
-use sdl2::event::Event;
+use clap::{App, ArgMatches};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::image::LoadTexture;
-use sdl2::image::InitFlag;
-use sdl2::keyboard::Keycode;
+use sdl2::image::{ImageRWops, InitFlag};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
 use sdl2::render::{Canvas, TextureCreator};
-use sdl2::video::{Window, WindowContext};
+use sdl2::rwops::RWops;
+use sdl2::ttf::{Font, Sdl2TtfContext};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 use sdl2::Sdl;
+use std::collections::VecDeque;
 use std::error::Error;
-use std::fs::File;
-use std::io::BufReader;
+use std::io;
 use std::path::Path;
-use std::str::FromStr;
-use xml::reader::EventReader;
-use xml::reader::XmlEvent;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+pub mod animation;
+pub mod assets;
+pub mod cells;
+pub mod collection;
+pub mod config;
+pub mod editor;
 pub mod error;
+pub mod floor;
 pub mod game;
+pub mod generator;
+pub mod grid;
+pub mod input;
+pub mod metrics;
 pub mod painter;
+pub mod scores;
 pub mod shadow;
+pub mod solver;
+pub mod status;
+pub mod theme;
 pub mod tileset;
 
-use game::{Direction, Level};
+use animation::Animation;
+use assets::Asset;
+use collection::Collection;
+use config::Config;
+use editor::{Editor, Tool};
+use game::{Level, Move, Position};
+use generator::{Generator, Settings};
+use input::{Action, KeyBindings, KeyRepeat};
+use metrics::Metrics;
 use painter::Painter;
-use tileset::Tileset;
+use scores::{Score, Scores};
+use status::{StatusField, StatusInfo, StatusLayout, Stopwatch};
+use theme::{Theme, TilesetDescriptor};
+use tileset::{Style, Tileset, TilesetSelector};
 
 pub fn main() -> Result<(), Box<dyn Error>> {
     // Read command line arguments
     let yml = load_yaml!("clap.yml");
     let matches = App::from_yaml(yml).get_matches();
-    let width = value_t!(matches.value_of("width"), u32).unwrap_or(1024);
-    let height = value_t!(matches.value_of("height"), u32).unwrap_or(768);
-    let fullscreen = matches.is_present("fullscreen");
+    if let Some(matches) = matches.subcommand_matches("generate") {
+        return generate(matches);
+    }
+    if let Some(matches) = matches.subcommand_matches("stats") {
+        return stats(matches);
+    }
     let slc_file = matches.value_of("slc_file").unwrap();
 
-    // Load the level collection file
-    let levels = load_slc_file(slc_file)?;
+    // Load the user configuration and let the command line override it
+    let mut config = Config::load(matches.value_of("config").map(Path::new))?;
+    if let Ok(width) = value_t!(matches.value_of("width"), u32) {
+        config.window.width = width;
+    }
+    if let Ok(height) = value_t!(matches.value_of("height"), u32) {
+        config.window.height = height;
+    }
+    if let Some(theme) = matches.value_of("theme") {
+        config.theme.name = theme.to_owned();
+    }
+    if matches.is_present("fullscreen") {
+        config.window.fullscreen = true;
+    }
+    if let Ok(size) = value_t!(matches.value_of("min_tile_size"), u32) {
+        config.window.min_tile_size = Some(size);
+    }
+    if matches.is_present("no_animation") {
+        config.gameplay.animation = false;
+    }
+    if let Ok(millis) = value_t!(matches.value_of("animation_duration"), u64) {
+        config.gameplay.animation = true;
+        config.gameplay.animation_duration = millis;
+    }
+    if let Ok(millis) = value_t!(matches.value_of("repeat_delay"), u64) {
+        config.gameplay.repeat_delay = millis;
+    }
+    if let Ok(millis) = value_t!(matches.value_of("repeat_interval"), u64) {
+        config.gameplay.repeat_interval = millis;
+    }
+
+    let controls = Controls {
+        bindings: config.key_bindings()?,
+        animation_duration: if config.gameplay.animation {
+            Some(Duration::from_millis(config.gameplay.animation_duration))
+        } else {
+            None
+        },
+        key_repeat: KeyRepeat::new(
+            Duration::from_millis(config.gameplay.repeat_delay),
+            Duration::from_millis(config.gameplay.repeat_interval),
+        ),
+    };
+
+    // Open the level collection file, either to play or to edit it
+    let mut mode = if matches.is_present("edit") {
+        Mode::Edit(Box::new(Editor::open(slc_file)?))
+    } else {
+        let scores = Scores::load().unwrap_or_else(|err| {
+            eprintln!("Could not load the scores: {}", err);
+            Scores::default()
+        });
+        Mode::Play(Collection::load(slc_file)?, scores)
+    };
 
     // Initialize SDL components
     let sdl = sdl2::init()?;
     let _ = sdl2::image::init(InitFlag::PNG)?;
     let ttf_context = sdl2::ttf::init()?;
 
-    let window = create_window(&sdl, width, height, fullscreen)?;
+    let window = create_window(
+        &sdl,
+        config.window.width,
+        config.window.height,
+        config.window.fullscreen,
+    )?;
     let mut canvas = window.into_canvas().build()?;
     let texture_creator = canvas.texture_creator();
 
     let mut painter = {
-        let big_set = load_tileset(
-            &texture_creator,
-            "assets/image/tileset.png",
-            101,
-            171,
-            83,
-            40,
-        )?;
-        let small_set = load_tileset(
-            &texture_creator,
-            "assets/image/tileset-small.png",
-            50,
-            85,
-            41,
-            20,
-        )?;
-        let font = ttf_context.load_font("assets/font/RujisHandwritingFontv.2.0.ttf", 20)?;
-        Painter::new(&mut canvas, big_set, small_set, font)
+        let theme = Theme::load(&config.theme.name)?;
+        let mut tilesets = Vec::new();
+        for descriptor in &theme.tilesets {
+            tilesets.push(load_tileset(&texture_creator, descriptor, theme.style)?);
+        }
+        let selector = TilesetSelector::new(tilesets);
+
+        let font_path = config.font.path.clone();
+        let font_size = config.font.size;
+        let ttf_context = &ttf_context;
+        let font_loader = Box::new(move |scale_factor: f64| {
+            let size = (f64::from(font_size) * scale_factor).round() as u16;
+            load_font(ttf_context, &font_path, size)
+        });
+
+        let mut painter = Painter::new(&mut canvas, &texture_creator, selector, font_loader)?;
+        let (r, g, b) = config.status_bar.color;
+        let (tr, tg, tb) = config.status_bar.text_color;
+        painter.set_bar_colors(Color::RGB(r, g, b), Color::RGB(tr, tg, tb));
+        painter.set_min_tile_size(config.window.min_tile_size);
+        painter.set_status_layout(StatusLayout {
+            left: config.status_bar.left.clone(),
+            center: config.status_bar.center.clone(),
+            right: config.status_bar.right.clone(),
+        });
+        painter
     };
 
-    mainloop(&sdl, levels.iter(), &mut painter, &mut canvas);
+    match mode {
+        Mode::Play(ref collection, ref mut scores) => mainloop(
+            &sdl,
+            collection,
+            scores,
+            &mut painter,
+            &mut canvas,
+            controls,
+        ),
+        Mode::Edit(ref mut editor) => editloop(&sdl, editor, &mut painter, &mut canvas, controls),
+    }
 
     Ok(())
 }
 
-/// Builds levels from a level collection file in the SLC format.
-fn load_slc_file<P: AsRef<Path>>(path: P) -> Result<Vec<Level>, error::SokobanError> {
-    let mut levels = Vec::new();
-
-    let parser = {
-        let file = File::open(path.as_ref())?;
-        EventReader::new(BufReader::new(file))
+/// Generates a collection of new levels and writes it to a file.
+fn generate(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let output = matches.value_of("output").unwrap();
+    let count = value_t!(matches.value_of("count"), usize)?;
+    let settings = Settings {
+        width: value_t!(matches.value_of("width"), i32)?,
+        height: value_t!(matches.value_of("height"), i32)?,
+        boxes: value_t!(matches.value_of("boxes"), usize)?,
+    };
+    let seed = match matches.value_of("seed") {
+        Some(_) => value_t!(matches.value_of("seed"), u64)?,
+        None => SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
     };
 
-    let mut level_title = String::new();
-    let mut level_data = String::new();
-    let mut reading_level = false;
-    for event in parser {
-        match event {
-            Ok(XmlEvent::StartElement {
-                ref name,
-                ref attributes,
-                ..
-            }) => {
-                if name.local_name == "L" {
-                    reading_level = true;
-                } else if name.local_name == "Level" {
-                    if let Some(id) = attributes.iter().find(|&attr| attr.name.local_name == "Id") {
-                        level_title = id.value.clone();
-                    }
-                }
-            }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "Level" {
-                    let mut level = Level::from_str(&level_data)?;
-                    level.set_title(level_title.clone());
-                    levels.push(level);
-                    level_data.clear();
-                }
-            }
-            Ok(XmlEvent::Characters(ref data)) => {
-                if reading_level {
-                    level_data.push_str(data);
-                    level_data.push('\n');
-                }
-            }
-            _ => {}
-        }
+    let mut generator = Generator::new(settings, seed)?;
+    let mut collection = Collection::new(format!("Generated levels (seed {})", seed));
+    for n in 1..=count {
+        let mut puzzle = generator.generate()?;
+        puzzle.level.set_title(format!("{}-{}", seed, n));
+        let solution = puzzle.lurd();
+        collection.push(puzzle.level);
+        collection.set_level_attribute(n - 1, "Solution", solution);
+    }
+    collection.save(output)?;
+    println!("Wrote {} levels to {} (seed {})", count, output, seed);
+    Ok(())
+}
+
+/// Prints the metrics of the levels of a collection.
+fn stats(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let collection = Collection::load(matches.value_of("slc_file").unwrap())?;
+    let budget = value_t!(matches.value_of("budget"), usize)?;
+    let mut rows: Vec<(usize, &Level, Metrics)> = collection
+        .levels()
+        .iter()
+        .enumerate()
+        .map(|(index, level)| (index, level, Metrics::compute(level, budget)))
+        .collect();
+    if matches.is_present("sort") {
+        // Unsolved levels come last
+        rows.sort_by(|a, b| {
+            (!a.2.is_solved(), a.2.difficulty)
+                .partial_cmp(&(!b.2.is_solved(), b.2.difficulty))
+                .unwrap()
+        });
     }
 
-    Ok(levels)
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    if matches.is_present("json") {
+        metrics::write_json(&mut out, &rows)?;
+    } else {
+        metrics::write_table(&mut out, &rows)?;
+    }
+    Ok(())
+}
+
+/// Represents what the game does with the level collection.
+enum Mode {
+    /// Play the levels and record the best scores
+    Play(Collection, Scores),
+    /// Edit the levels
+    Edit(Box<Editor>),
 }
 
 /// Creates the SDL window
@@ -156,100 +284,721 @@ fn create_window(
     } else {
         window_builder.position_centered();
     }
-    let window = window_builder.opengl().build()?;
+    let window = window_builder
+        .opengl()
+        .resizable()
+        .allow_highdpi()
+        .build()?;
     Ok(window)
 }
 
 /// Loads a tileset
-fn load_tileset<P: AsRef<Path>>(
-    texture_creator: &TextureCreator<WindowContext>,
-    path: P,
-    width: u32,
-    height: u32,
-    effective_height: u32,
-    offset: i32,
-) -> Result<Tileset, Box<dyn Error>> {
-    let texture = texture_creator.load_texture(path.as_ref())?;
-    let tileset = Tileset::new(texture, width, height, effective_height, offset);
+fn load_tileset<'a>(
+    texture_creator: &'a TextureCreator<WindowContext>,
+    descriptor: &TilesetDescriptor,
+    style: Style,
+) -> Result<Tileset<'a>, Box<dyn Error>> {
+    let texture = match descriptor.image()? {
+        Asset::File(path) => texture_creator.load_texture(path)?,
+        Asset::Embedded(bytes) => {
+            let rwops = RWops::from_bytes(bytes)?;
+            let surface = rwops.load()?;
+            texture_creator.create_texture_from_surface(surface)?
+        }
+    };
+    let tileset = Tileset::new(
+        texture,
+        descriptor.width,
+        descriptor.height,
+        descriptor.effective_height(),
+        descriptor.offset(),
+        style,
+        descriptor.locations(style)?,
+    );
     Ok(tileset)
 }
 
+/// Loads a font
+fn load_font<'a, P: AsRef<Path>>(
+    ttf_context: &'a Sdl2TtfContext,
+    path: P,
+    size: u16,
+) -> Result<Font<'a, 'static>, Box<dyn Error>> {
+    let font = match assets::locate(path)? {
+        Asset::File(path) => ttf_context.load_font(path, size)?,
+        Asset::Embedded(bytes) => {
+            ttf_context.load_font_from_rwops(RWops::from_bytes(bytes)?, size)?
+        }
+    };
+    Ok(font)
+}
+
+/// The time between two frames while something is moving on the screen
+const FRAME_DURATION_MS: u32 = 16;
+
+/// The time between two frames while the timer is running
+const TIMER_REFRESH_MS: u32 = 100;
+
+/// The duration of each move when replaying a solution with animations disabled
+const REPLAY_STEP_MS: u64 = 100;
+
+/// The factor by which the zoom level changes at each step
+const ZOOM_STEP: f64 = 1.1;
+
+/// The distance in screen coordinates by which the view scrolls at each step
+const PAN_STEP: i32 = 64;
+
+/// Represents the way the player controls the game.
+struct Controls {
+    /// The actions bound to the keys
+    bindings: KeyBindings,
+    /// The duration of the animation of a move, if moves are animated
+    animation_duration: Option<Duration>,
+    /// The repetition of held direction keys
+    key_repeat: KeyRepeat,
+}
+
+/// Represents the result of the completion of a level.
+#[derive(Copy, Clone)]
+struct Completion {
+    /// The score obtained
+    score: Score,
+    /// Whether the score is a new personal best
+    is_best: bool,
+    /// The best score recorded before this one
+    previous_best: Option<Score>,
+}
+
+/// Represents what the main loop is showing.
+enum Screen {
+    /// The player is solving the level
+    Playing,
+    /// The level has been completed
+    Completed(Completion),
+    /// The solution of a completed level is being played back
+    Replaying(Completion, VecDeque<Move>),
+    /// The end of the collection has been reached
+    Summary,
+}
+
+/// Returns a fresh copy of a level, to be played forward or in reverse.
+///
+/// Levels with several players or special floors are always played forward.
+fn start_level(level: &Level, reverse: bool) -> Level {
+    let reversed = if reverse { level.reversed() } else { None };
+    reversed.unwrap_or_else(|| level.clone())
+}
+
 /// Main game event loop
-fn mainloop<'a, I: Iterator<Item = &'a Level>>(
+fn mainloop(
     sdl: &Sdl,
-    mut levels: I,
+    collection: &Collection,
+    scores: &mut Scores,
     painter: &mut Painter,
     canvas: &mut Canvas<Window>,
+    controls: Controls,
 ) {
-    let (mut reference_level, mut level) = match levels.next() {
-        Some(l) => (l, l.clone()),
-        None => {
-            return;
-        }
-    };
+    let Controls {
+        bindings,
+        animation_duration,
+        mut key_repeat,
+    } = controls;
+    let replay_duration = animation_duration.unwrap_or(Duration::from_millis(REPLAY_STEP_MS));
 
+    let levels = collection.levels();
+    if levels.is_empty() {
+        return;
+    }
+
+    let mut index = 0;
+    let mut reverse = false;
+    let mut level = levels[index].clone();
+    let mut next_index = None;
+    let mut screen = Screen::Playing;
+    let mut results: Vec<Option<Score>> = vec![None; levels.len()];
     let mut running = true;
     let mut events = sdl.event_pump().unwrap();
-    let mut skip = false;
+    let mut animation: Option<Animation> = None;
+    let mut pending_moves = VecDeque::new();
+    let mut stopwatch = Stopwatch::default();
     while running {
-        if level.is_completed() || skip {
-            match levels.next() {
-                Some(l) => {
-                    reference_level = l;
-                    level = l.clone();
-                    skip = false;
+        if animation.as_ref().is_some_and(|anim| anim.is_finished()) {
+            animation = None;
+        }
+
+        match screen {
+            Screen::Playing => {
+                // Keep moving while a direction key is held
+                if pending_moves.is_empty() {
+                    if let Some(dir) = key_repeat.poll() {
+                        pending_moves.push_back(dir);
+                    }
+                }
+
+                // Moves requested during an animation are played one after the other
+                if animation.is_none() {
+                    if let Some(dir) = pending_moves.pop_front() {
+                        // Boxes are pulled in reverse unless Shift is held
+                        let shift = Mod::LSHIFTMOD | Mod::RSHIFTMOD;
+                        let movement = if level.is_reversed()
+                            && !sdl.keyboard().mod_state().intersects(shift)
+                        {
+                            level.pull(dir)
+                        } else {
+                            level.step(dir)
+                        };
+                        if movement.is_some() {
+                            stopwatch.start();
+                        }
+                        animation = movement
+                            .and_then(|mv| animation_duration.map(|d| Animation::new(mv, d)));
+                    }
+                }
+
+                if level.is_completed() && animation.is_none() {
+                    stopwatch.stop();
+                    pending_moves.clear();
+                    key_repeat.release_all();
+
+                    // Turn a game played in reverse into the solution of the level
+                    if let Some(solution) = level.forward_solution() {
+                        level = levels[index].clone();
+                        for dir in solution {
+                            level.step(dir);
+                        }
+                        painter.reset(canvas, &level);
+                    }
+
+                    let score =
+                        Score::new(level.get_steps(), level.get_pushes(), stopwatch.elapsed());
+                    let previous_best = scores.best(collection.title(), level.title());
+                    let is_best = previous_best.is_none_or(|best| score.is_better_than(&best));
+                    if let Err(err) = scores.record(collection.title(), level.title(), score) {
+                        eprintln!("Could not save the score: {}", err);
+                    }
+                    if results[index].is_none_or(|result| score.is_better_than(&result)) {
+                        results[index] = Some(score);
+                    }
+                    screen = Screen::Completed(Completion {
+                        score,
+                        is_best,
+                        previous_best,
+                    });
                 }
-                None => {
-                    break;
+            }
+            Screen::Replaying(completion, ref mut replay) => {
+                if animation.is_none() {
+                    match replay.pop_front() {
+                        Some(mv) => {
+                            level.select_player(mv.player());
+                            animation = level
+                                .step(mv.direction())
+                                .map(|mv| Animation::new(mv, replay_duration));
+                        }
+                        None => screen = Screen::Completed(completion),
+                    }
                 }
             }
+            Screen::Completed(..) | Screen::Summary => {}
         }
 
-        painter.paint(canvas, &level);
+        if let Some(i) = next_index.take() {
+            if i >= levels.len() {
+                screen = Screen::Summary;
+            } else {
+                index = i;
+                level = start_level(&levels[index], reverse);
+                screen = Screen::Playing;
+                animation = None;
+                pending_moves.clear();
+                stopwatch.reset();
+                painter.reset(canvas, &level);
+            }
+        }
+
+        let message = if level.is_reversed() {
+            "Reverse".to_owned()
+        } else if level.player_count() > 1 {
+            format!(
+                "Player {}/{}",
+                level.active_player() + 1,
+                level.player_count()
+            )
+        } else {
+            String::new()
+        };
+        let info = StatusInfo {
+            level_number: index + 1,
+            level_count: levels.len(),
+            collection: collection.title(),
+            elapsed: stopwatch.elapsed(),
+            best: scores.best(collection.title(), level.title()),
+            message: &message,
+        };
+        let overlay = match screen {
+            Screen::Completed(completion) => Some(status::completion_lines(
+                completion.score,
+                completion.is_best,
+                completion.previous_best,
+                &bindings,
+            )),
+            Screen::Summary => Some(status::summary_lines(
+                collection.title(),
+                &results,
+                &bindings,
+            )),
+            Screen::Playing | Screen::Replaying(..) => None,
+        };
+        painter.paint(
+            canvas,
+            &level,
+            animation.as_ref(),
+            &info,
+            overlay.as_deref(),
+        );
 
-        match events.wait_event() {
-            Event::Quit { .. }
-            | Event::KeyDown {
-                keycode: Some(Keycode::Escape),
+        let is_moving = animation.is_some()
+            || !pending_moves.is_empty()
+            || key_repeat.is_active()
+            || matches!(screen, Screen::Replaying(..));
+        let event = if is_moving {
+            events.wait_event_timeout(FRAME_DURATION_MS)
+        } else if stopwatch.is_running() {
+            events.wait_event_timeout(TIMER_REFRESH_MS)
+        } else {
+            Some(events.wait_event())
+        };
+
+        match event {
+            Some(Event::Quit { .. }) => running = false,
+            Some(Event::KeyDown {
+                keycode: Some(keycode),
+                repeat,
+                ..
+            }) => match bindings.action(keycode) {
+                Some(Action::Quit) => running = false,
+                Some(Action::Restart) => {
+                    level = start_level(&levels[index], reverse);
+                    screen = Screen::Playing;
+                    animation = None;
+                    pending_moves.clear();
+                    stopwatch.reset();
+                    painter.reset(canvas, &level);
+                }
+                Some(Action::Reverse) => {
+                    reverse = !reverse;
+                    level = start_level(&levels[index], reverse);
+                    screen = Screen::Playing;
+                    animation = None;
+                    pending_moves.clear();
+                    stopwatch.reset();
+                    painter.reset(canvas, &level);
+                }
+                Some(Action::SwitchPlayer) => {
+                    if let Screen::Playing = screen {
+                        animation = None;
+                        pending_moves.clear();
+                        level.switch_player();
+                    }
+                }
+                Some(Action::NextLevel) => {
+                    next_index = Some(index + 1);
+                }
+                Some(Action::PreviousLevel) => {
+                    next_index = match screen {
+                        Screen::Summary => Some(index),
+                        _ => Some(index.saturating_sub(1)),
+                    };
+                }
+                Some(Action::ToggleFullscreen) => {
+                    toggle_fullscreen(canvas);
+                    painter.resize(canvas);
+                }
+                Some(Action::ZoomIn) => painter.zoom(ZOOM_STEP),
+                Some(Action::ZoomOut) => painter.zoom(1.0 / ZOOM_STEP),
+                Some(Action::ZoomReset) => painter.reset_zoom(),
+                Some(Action::PanUp) => painter.pan(0, -PAN_STEP),
+                Some(Action::PanDown) => painter.pan(0, PAN_STEP),
+                Some(Action::PanLeft) => painter.pan(-PAN_STEP, 0),
+                Some(Action::PanRight) => painter.pan(PAN_STEP, 0),
+                Some(action) => match screen {
+                    Screen::Playing => match action {
+                        Action::Undo => {
+                            animation = None;
+                            pending_moves.clear();
+                            level.undo();
+                        }
+                        Action::Redo => {
+                            animation = None;
+                            pending_moves.clear();
+                            level.redo();
+                        }
+                        // Repeated key events are ignored, the game handles them itself
+                        _ => {
+                            if let Some(dir) = action.direction() {
+                                if !repeat {
+                                    pending_moves.push_back(dir);
+                                    key_repeat.press(dir);
+                                }
+                            }
+                        }
+                    },
+                    Screen::Completed(completion) => match action {
+                        Action::Continue => next_index = Some(index + 1),
+                        Action::Replay => {
+                            let replay = level.moves().iter().cloned().collect();
+                            level = levels[index].clone();
+                            animation = None;
+                            screen = Screen::Replaying(completion, replay);
+                        }
+                        _ => {}
+                    },
+                    Screen::Replaying(completion, ref mut replay) => {
+                        // Skip to the end of the solution
+                        if action == Action::Continue {
+                            for mv in replay.drain(..) {
+                                level.select_player(mv.player());
+                                level.step(mv.direction());
+                            }
+                            animation = None;
+                            screen = Screen::Completed(completion);
+                        }
+                    }
+                    Screen::Summary => {
+                        if action == Action::Continue {
+                            running = false;
+                        }
+                    }
+                },
+                None => {}
+            },
+            Some(Event::KeyUp {
+                keycode: Some(keycode),
                 ..
-            } => running = false,
-            Event::KeyDown {
-                keycode: Some(Keycode::Left),
+            }) => {
+                if let Some(dir) = bindings.action(keycode).and_then(|a| a.direction()) {
+                    key_repeat.release(dir);
+                }
+            }
+            Some(Event::MouseMotion {
+                mousestate,
+                xrel,
+                yrel,
                 ..
-            } => {
-                level.step(Direction::Left);
+            }) if mousestate.left() || mousestate.right() => {
+                // Drag the view around
+                painter.pan(-xrel, -yrel);
+            }
+            Some(Event::MouseWheel { y, .. }) => {
+                painter.zoom(ZOOM_STEP.powi(y));
+            }
+            Some(Event::Window {
+                win_event: WindowEvent::SizeChanged(..),
+                ..
+            }) => {
+                painter.resize(canvas);
+            }
+            Some(Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            }) => {
+                key_repeat.release_all();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns true if the editor may quit.
+///
+/// When there are unsaved changes, the first request only warns the user, and
+/// quitting takes a second one.
+fn confirm_quit(editor: &Editor, warned: &mut bool, notice: &mut Option<String>) -> bool {
+    if !editor.is_modified() || *warned {
+        return true;
+    }
+    *warned = true;
+    *notice = Some(String::from(
+        "unsaved changes: Ctrl+S to save, quit again to discard them",
+    ));
+    false
+}
+
+/// Level editor event loop
+fn editloop(
+    sdl: &Sdl,
+    editor: &mut Editor,
+    painter: &mut Painter,
+    canvas: &mut Canvas<Window>,
+    controls: Controls,
+) {
+    let Controls {
+        bindings,
+        animation_duration,
+        ..
+    } = controls;
+    painter.set_status_layout(StatusLayout {
+        left: vec![StatusField::Message],
+        center: Vec::new(),
+        right: vec![StatusField::Level, StatusField::Title],
+    });
+
+    // The copy of the edited level being test-played, if any
+    let mut test: Option<Level> = None;
+    let mut notice: Option<String> = None;
+    // Whether the user was warned that quitting loses the unsaved changes
+    let mut quit_warned = false;
+    let mut needs_reset = true;
+    let mut last_drawn: Option<Position> = None;
+    let mut running = true;
+    let mut events = sdl.event_pump().unwrap();
+    let mut animation: Option<Animation> = None;
+    while running {
+        if animation.as_ref().is_some_and(|anim| anim.is_finished()) {
+            animation = None;
+        }
+
+        let test_key = bindings.key_name(Action::TestPlay).unwrap_or("?");
+        let message = match test {
+            Some(ref level) if level.is_completed() => format!(
+                "solved in {} moves, {}: back to the editor",
+                level.get_steps(),
+                test_key
+            ),
+            Some(ref level) => format!(
+                "testing: {} moves, {}: back to the editor",
+                level.get_steps(),
+                test_key
+            ),
+            None => notice.clone().unwrap_or_else(|| {
+                let (cols, rows) = editor.level().extents();
+                let modified = if editor.is_modified() {
+                    " (modified)"
+                } else {
+                    ""
+                };
+                format!(
+                    "tool: {}    size: {} x {}{}",
+                    editor.tool().name(),
+                    cols,
+                    rows,
+                    modified
+                )
+            }),
+        };
+        let level = test.as_ref().unwrap_or_else(|| editor.level());
+        if needs_reset {
+            painter.reset(canvas, level);
+            needs_reset = false;
+        }
+        let info = StatusInfo {
+            level_number: editor.index() + 1,
+            level_count: editor.level_count(),
+            collection: editor.title(),
+            elapsed: Duration::from_secs(0),
+            best: None,
+            message: &message,
+        };
+        painter.paint(canvas, level, animation.as_ref(), &info, None);
+
+        let event = if animation.is_some() {
+            events.wait_event_timeout(FRAME_DURATION_MS)
+        } else {
+            Some(events.wait_event())
+        };
+
+        match event {
+            Some(Event::Quit { .. }) => {
+                running = !confirm_quit(editor, &mut quit_warned, &mut notice);
+                if running {
+                    test = None;
+                    needs_reset = true;
+                }
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::Right),
+            Some(Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
                 ..
-            } => {
-                level.step(Direction::Right);
+            }) if bindings.action(keycode) == Some(Action::TestPlay)
+                && !keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+            {
+                // Switch between editing and test-playing
+                test = match test {
+                    Some(_) => None,
+                    None => match editor.level().check() {
+                        Ok(()) => Some(editor.level().clone()),
+                        Err(err) => {
+                            notice = Some(format!("cannot test: {}", err));
+                            None
+                        }
+                    },
+                };
+                animation = None;
+                needs_reset = true;
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::Up),
+            Some(Event::KeyDown {
+                keycode: Some(keycode),
+                keymod,
                 ..
-            } => {
-                level.step(Direction::Up);
+            }) if test.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                notice = None;
+                quit_warned = false;
+                match keycode {
+                    Keycode::S => {
+                        notice = Some(match editor.save() {
+                            Ok(path) => format!("saved to {}", path.display()),
+                            Err(err) => format!("cannot save: {}", err),
+                        });
+                    }
+                    Keycode::N => editor.new_level(),
+                    Keycode::Left => editor.resize(-1, 0),
+                    Keycode::Right => editor.resize(1, 0),
+                    Keycode::Up => editor.resize(0, -1),
+                    Keycode::Down => editor.resize(0, 1),
+                    _ => {}
+                }
+                needs_reset = true;
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::Down),
+            Some(Event::KeyDown {
+                keycode: Some(keycode),
+                repeat,
                 ..
-            } => {
-                level.step(Direction::Down);
+            }) => match bindings.action(keycode) {
+                Some(Action::Quit) => {
+                    running = !confirm_quit(editor, &mut quit_warned, &mut notice);
+                    if running {
+                        test = None;
+                        needs_reset = true;
+                    }
+                }
+                Some(Action::ToggleFullscreen) => {
+                    toggle_fullscreen(canvas);
+                    painter.resize(canvas);
+                }
+                Some(Action::ZoomIn) => painter.zoom(ZOOM_STEP),
+                Some(Action::ZoomOut) => painter.zoom(1.0 / ZOOM_STEP),
+                Some(Action::ZoomReset) => painter.reset_zoom(),
+                Some(Action::PanUp) => painter.pan(0, -PAN_STEP),
+                Some(Action::PanDown) => painter.pan(0, PAN_STEP),
+                Some(Action::PanLeft) => painter.pan(-PAN_STEP, 0),
+                Some(Action::PanRight) => painter.pan(PAN_STEP, 0),
+                action => match test {
+                    Some(ref mut level) => match action {
+                        Some(Action::Undo) => {
+                            animation = None;
+                            level.undo();
+                        }
+                        Some(Action::Redo) => {
+                            animation = None;
+                            level.redo();
+                        }
+                        Some(Action::Restart) => {
+                            animation = None;
+                            *level = editor.level().clone();
+                        }
+                        Some(Action::SwitchPlayer) => {
+                            animation = None;
+                            level.switch_player();
+                        }
+                        Some(action) => {
+                            if let Some(dir) = action.direction() {
+                                if !repeat && animation.is_none() {
+                                    animation = level.step(dir).and_then(|mv| {
+                                        animation_duration.map(|d| Animation::new(mv, d))
+                                    });
+                                }
+                            }
+                        }
+                        None => {}
+                    },
+                    None => {
+                        notice = None;
+                        quit_warned = false;
+                        match (action, keycode) {
+                            (Some(Action::NextLevel), _) => {
+                                editor.select(editor.index() + 1);
+                                needs_reset = true;
+                            }
+                            (Some(Action::PreviousLevel), _) => {
+                                editor.select(editor.index().saturating_sub(1));
+                                needs_reset = true;
+                            }
+                            (_, Keycode::Num1) => editor.set_tool(Tool::Wall),
+                            (_, Keycode::Num2) => editor.set_tool(Tool::Floor),
+                            (_, Keycode::Num3) => editor.set_tool(Tool::Goal),
+                            (_, Keycode::Num4) => editor.set_tool(Tool::Box),
+                            (_, Keycode::Num5) => editor.set_tool(Tool::Player),
+                            _ => {}
+                        }
+                    }
+                },
+            },
+            Some(Event::MouseButtonDown {
+                mouse_btn, x, y, ..
+            }) if test.is_none()
+                && (mouse_btn == MouseButton::Left || mouse_btn == MouseButton::Right) =>
+            {
+                if let Some(pos) = painter.position_at(editor.level(), x, y) {
+                    if mouse_btn == MouseButton::Left {
+                        editor.draw(pos);
+                    } else {
+                        editor.erase(pos);
+                    }
+                    notice = None;
+                    quit_warned = false;
+                    last_drawn = Some(pos);
+                    needs_reset = true;
+                }
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::R),
+            Some(Event::MouseMotion {
+                mousestate,
+                x,
+                y,
+                xrel,
+                yrel,
                 ..
-            } => {
-                level = reference_level.clone();
+            }) => {
+                if test.is_none() && (mousestate.left() || mousestate.right()) {
+                    // Keep drawing while the mouse is dragged
+                    match painter.position_at(editor.level(), x, y) {
+                        Some(pos) if last_drawn != Some(pos) => {
+                            if mousestate.left() {
+                                editor.draw(pos);
+                            } else {
+                                editor.erase(pos);
+                            }
+                            last_drawn = Some(pos);
+                            needs_reset = true;
+                        }
+                        _ => {}
+                    }
+                } else if mousestate.left() || mousestate.right() || mousestate.middle() {
+                    // Drag the view around
+                    painter.pan(-xrel, -yrel);
+                }
             }
-            Event::KeyDown {
-                keycode: Some(Keycode::N),
+            Some(Event::MouseWheel { y, .. }) => {
+                painter.zoom(ZOOM_STEP.powi(y));
+            }
+            Some(Event::Window {
+                win_event: WindowEvent::SizeChanged(..),
                 ..
-            } => {
-                skip = true;
+            }) => {
+                painter.resize(canvas);
             }
             _ => {}
         }
     }
 }
+
+/// Switches the window between fullscreen and windowed mode.
+fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+    let window = canvas.window_mut();
+    let mode = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    if let Err(err) = window.set_fullscreen(mode) {
+        eprintln!("Could not toggle fullscreen mode: {}", err);
+    }
+}
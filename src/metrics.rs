@@ -0,0 +1,223 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measures how hard levels are.
+
+use game::{Direction, Level, Position};
+use solver::{self, Search};
+use std::io::{self, Write};
+
+/// Represents the metrics of a level.
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    /// The number of boxes
+    pub boxes: usize,
+    /// The number of positions the player could walk to if there were no boxes
+    pub floor: usize,
+    /// The number of positions from which a box can never reach a square
+    pub dead_squares: usize,
+    /// The number of positions between two walls facing each other
+    pub tunnels: usize,
+    /// The number of pushes of the optimal solution, if one was found
+    pub pushes: Option<usize>,
+    /// The number of moves of the optimal solution, if one was found
+    pub moves: Option<usize>,
+    /// The number of positions explored while searching for the solution with the fewest pushes
+    pub effort: usize,
+    /// The difficulty rating
+    pub difficulty: f64,
+    /// False if the level has several players, which the solver cannot move in turn
+    pub supported: bool,
+}
+
+impl Metrics {
+    /// Computes the metrics of a level.
+    ///
+    /// Searches for optimal solutions explore at most `budget` positions each.
+    /// Levels with several players are not searched.
+    pub fn compute(level: &Level, budget: usize) -> Metrics {
+        let area = solver::floor_area(level);
+        let tunnels = area.iter().filter(|pos| is_tunnel(level, pos)).count();
+
+        let supported = level.player_count() == 1;
+        let (push_search, move_search) = if supported {
+            (
+                solver::solve_pushes(level, budget),
+                solver::solve_moves(level, budget),
+            )
+        } else {
+            let unsearched = || Search {
+                solution: None,
+                explored: 0,
+            };
+            (unsearched(), unsearched())
+        };
+        let pushes = push_search
+            .solution
+            .as_ref()
+            .map(|solution| count_pushes(level, solution));
+        let moves = move_search.solution.map(|solution| solution.len());
+
+        let mut metrics = Metrics {
+            boxes: level.box_count(),
+            floor: area.len(),
+            dead_squares: solver::dead_squares(level).len(),
+            tunnels,
+            pushes,
+            moves,
+            effort: push_search.explored,
+            difficulty: 0.0,
+            supported,
+        };
+        metrics.difficulty = metrics.rate();
+        metrics
+    }
+
+    /// Returns true if a solution was found within the budget.
+    pub fn is_solved(&self) -> bool {
+        self.pushes.is_some()
+    }
+
+    /// Combines the metrics into a difficulty rating.
+    ///
+    /// The rating grows with the logarithm of the search effort, which tells how
+    /// many wrong paths there are, and with the length of the solution. Levels
+    /// that could not be solved within the budget rate with the whole budget and
+    /// without the length of their solution.
+    fn rate(&self) -> f64 {
+        let effort = 2.0 * (1.0 + self.effort as f64).log2();
+        let length = self.pushes.unwrap_or(0) as f64 / 5.0;
+        let crowd = self.boxes as f64;
+        ((effort + length + crowd) * 10.0).round() / 10.0
+    }
+}
+
+/// Writes the metrics of levels as a table.
+pub fn write_table<W: Write>(out: &mut W, rows: &[(usize, &Level, Metrics)]) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:>4}  {:<20}  {:>5}  {:>5}  {:>4}  {:>7}  {:>6}  {:>6}  {:>8}  {:>10}",
+        "#",
+        "title",
+        "boxes",
+        "floor",
+        "dead",
+        "tunnels",
+        "pushes",
+        "moves",
+        "effort",
+        "difficulty"
+    )?;
+    for &(index, level, ref metrics) in rows {
+        // Levels the solver cannot search have no counts rather than unknown ones
+        let blank = if metrics.supported { "-" } else { "n/a" };
+        let count =
+            |value: Option<usize>| value.map_or_else(|| blank.to_string(), |v| v.to_string());
+        let (effort, difficulty) = if metrics.supported {
+            (
+                metrics.effort.to_string(),
+                format!("{:.1}", metrics.difficulty),
+            )
+        } else {
+            (blank.to_string(), blank.to_string())
+        };
+        writeln!(
+            out,
+            "{:>4}  {:<20}  {:>5}  {:>5}  {:>4}  {:>7}  {:>6}  {:>6}  {:>8}  {:>10}",
+            index + 1,
+            level.title(),
+            metrics.boxes,
+            metrics.floor,
+            metrics.dead_squares,
+            metrics.tunnels,
+            count(metrics.pushes),
+            count(metrics.moves),
+            effort,
+            difficulty
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the metrics of levels as a JSON array.
+pub fn write_json<W: Write>(out: &mut W, rows: &[(usize, &Level, Metrics)]) -> io::Result<()> {
+    let count =
+        |value: Option<usize>| value.map_or_else(|| String::from("null"), |v| v.to_string());
+    writeln!(out, "[")?;
+    for (i, &(index, level, ref metrics)) in rows.iter().enumerate() {
+        let separator = if i + 1 < rows.len() { "," } else { "" };
+        let difficulty = if metrics.supported {
+            format!("{:.1}", metrics.difficulty)
+        } else {
+            String::from("null")
+        };
+        writeln!(
+            out,
+            "  {{\"index\": {}, \"title\": {}, \"boxes\": {}, \"floor\": {}, \
+             \"dead_squares\": {}, \"tunnels\": {}, \"pushes\": {}, \"moves\": {}, \
+             \"effort\": {}, \"solved\": {}, \"supported\": {}, \"difficulty\": {}}}{}",
+            index + 1,
+            json_string(level.title()),
+            metrics.boxes,
+            metrics.floor,
+            metrics.dead_squares,
+            metrics.tunnels,
+            count(metrics.pushes),
+            count(metrics.moves),
+            metrics.effort,
+            metrics.is_solved(),
+            metrics.supported,
+            difficulty,
+            separator
+        )?;
+    }
+    writeln!(out, "]")
+}
+
+/// Returns a string quoted and escaped for JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Returns the number of pushes made by the given moves.
+fn count_pushes(level: &Level, moves: &[Direction]) -> usize {
+    let mut level = level.clone();
+    moves
+        .iter()
+        .filter_map(|&dir| level.step(dir))
+        .filter(|mv| mv.is_push())
+        .count()
+}
+
+/// Returns true if the given position lies between two walls facing each other.
+fn is_tunnel(level: &Level, pos: &Position) -> bool {
+    let wall = |dir| level.is_wall(&level.grid().neighbor(pos, dir));
+    level
+        .grid()
+        .directions()
+        .iter()
+        .any(|&dir| wall(dir) && wall(dir.opposite()))
+}
@@ -14,185 +14,648 @@
 // limitations under the License.
 
 use sdl2::pixels::{Color, PixelFormatEnum};
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
 use sdl2::ttf::Font;
-use sdl2::video::Window;
+use sdl2::video::{Window, WindowContext};
 
-use game::{Direction, Level, Position};
+use std::collections::HashMap;
+use std::error::Error;
+
+use animation::Animation;
+use floor::Floor;
+use game::{Direction, Level, Position, MAX_COLOR};
+use grid::Grid;
 use shadow::ShadowFlags;
-use tileset::{Tile, Tileset, TilesetSelector};
+use status::{StatusInfo, StatusLayout};
+use tileset::{Style, Tile, Tileset, TilesetSelector};
+
+/// The tints of the coloured boxes and squares
+const PALETTE: [(u8, u8, u8); MAX_COLOR as usize] = [
+    (255, 96, 96),
+    (96, 160, 255),
+    (96, 224, 96),
+    (255, 224, 64),
+    (208, 112, 255),
+    (255, 160, 64),
+    (64, 224, 224),
+    (255, 128, 208),
+    (160, 160, 160),
+];
+
+/// Loads the font at the size matching the given number of pixels per screen coordinate.
+pub type FontLoader<'a> = Box<dyn Fn(f64) -> Result<Font<'a, 'a>, Box<dyn Error>> + 'a>;
 
 /// The Painter struct is responsible for drawing the game onto the screen.
 pub struct Painter<'a> {
+    /// The texture creator used for off-screen buffers
+    creator: &'a TextureCreator<WindowContext>,
     /// The tileset selector
     selector: TilesetSelector<'a>,
+    /// The static layer (floor, shadows and walls) of the current level
+    background: Option<Texture<'a>>,
+    /// The off-screen buffer onto which the full-size level is composited
+    frame: Option<Texture<'a>>,
     /// The font used to display text
     font: Font<'a, 'a>,
+    /// Loads the font again when the scale factor changes
+    load_font: FontLoader<'a>,
     /// The size of the screen in pixels
     screen_size: (u32, u32),
+    /// The ratio between pixels and screen coordinates
+    scale_factor: f64,
+    /// The zoom level, 1.0 meaning that the level fits onto the screen
+    zoom: f64,
+    /// The minimum width of the tiles on the screen, in screen coordinates
+    min_tile_size: Option<u32>,
+    /// The position of the visible part of a level bigger than the screen
+    camera: (i32, i32),
+    /// The player position the camera was last adjusted to
+    followed: Option<Position>,
+    /// The part of the screen where the level was last drawn
+    level_rect: Option<Rect>,
     /// The height of the status bar
     bar_height: u32,
     /// The color of the status bar
     bar_color: Color,
     /// The color of the text in the status bar
     bar_text_color: Color,
+    /// The fields shown in each part of the status bar
+    status_layout: StatusLayout,
+    /// The text last rendered in each slot of the status bar
+    text_cache: HashMap<(StatusBarLocation, usize), CachedText<'a>>,
+    /// The lines last rendered in the overlay
+    overlay_cache: Vec<CachedText<'a>>,
+}
+
+/// Represents a piece of text rendered into a texture.
+struct CachedText<'a> {
+    /// The rendered text
+    text: String,
+    /// The color of the rendered text
+    color: Color,
+    /// The texture holding the rendered text
+    texture: Texture<'a>,
 }
 
 /// Represents a location for text in the status bar
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 enum StatusBarLocation {
     FlushLeft,
+    Center,
     FlushRight,
 }
 
 impl<'a> Painter<'a> {
-    /// Creates a new instance.
+    const MIN_ZOOM: f64 = 0.1;
+    const MAX_ZOOM: f64 = 10.0;
+    /// The number of tiles kept visible between the player and the edges of the screen
+    const SCROLL_MARGIN: f64 = 3.0;
+
+    /// Creates a new instance, with the font loaded for the scale factor of the canvas.
     pub fn new(
         canvas: &mut Canvas<Window>,
-        big_set: Tileset<'a>,
-        small_set: Tileset<'a>,
-        font: Font<'a, 'a>,
-    ) -> Painter<'a> {
+        creator: &'a TextureCreator<WindowContext>,
+        selector: TilesetSelector<'a>,
+        load_font: FontLoader<'a>,
+    ) -> Result<Painter<'a>, Box<dyn Error>> {
         let screen_size = canvas.window().drawable_size();
-        let selector = TilesetSelector::new(big_set, small_set);
-        Painter {
+        let scale_factor = get_scale_factor(canvas);
+        Ok(Painter {
+            creator,
             selector,
-            font,
+            background: None,
+            frame: None,
+            font: load_font(scale_factor)?,
+            load_font,
             screen_size,
-            bar_height: 32,
+            scale_factor,
+            zoom: 1.0,
+            min_tile_size: None,
+            camera: (0, 0),
+            followed: None,
+            level_rect: None,
+            bar_height: scale(32, scale_factor),
             bar_color: Color::RGBA(20, 20, 20, 255),
             bar_text_color: Color::RGBA(255, 192, 0, 255),
+            status_layout: StatusLayout::default(),
+            text_cache: HashMap::new(),
+            overlay_cache: Vec::new(),
+        })
+    }
+
+    /// Prepares the painter for drawing a new level.
+    ///
+    /// The static layer of the level is rendered once here and reused by
+    /// every subsequent call to `paint` until the next reset.
+    pub fn reset(&mut self, canvas: &mut Canvas<Window>, level: &Level) {
+        // Pick the tileset that is closest to the size of the tiles on the screen
+        let biggest = self.selector.biggest();
+        let tile_width = self.get_ratio(biggest, level) * f64::from(biggest.width());
+        self.selector.reset(tile_width);
+
+        let fullsize = self.tileset().get_rendering_size(level.extents());
+        let mut background = self.create_fullsize_texture(fullsize);
+        canvas
+            .with_texture_canvas(&mut background, |cv| {
+                self.paint_background(cv, level);
+            })
+            .unwrap();
+
+        self.background = Some(background);
+        self.frame = Some(self.create_fullsize_texture(fullsize));
+        self.followed = None;
+    }
+
+    /// Changes the minimum width of the tiles on the screen.
+    ///
+    /// Levels that cannot fit onto the screen with tiles of this size are
+    /// scrolled so as to follow the player.
+    pub fn set_min_tile_size(&mut self, min_tile_size: Option<u32>) {
+        self.min_tile_size = min_tile_size;
+        self.invalidate();
+    }
+
+    /// Moves the visible part of a level bigger than the screen by the given
+    /// distance in screen coordinates.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.camera.0 += (f64::from(dx) * self.scale_factor).round() as i32;
+        self.camera.1 += (f64::from(dy) * self.scale_factor).round() as i32;
+    }
+
+    /// Changes the colors of the status bar and of its text.
+    pub fn set_bar_colors(&mut self, bar_color: Color, bar_text_color: Color) {
+        self.bar_color = bar_color;
+        self.bar_text_color = bar_text_color;
+    }
+
+    /// Changes the fields shown in the status bar.
+    pub fn set_status_layout(&mut self, layout: StatusLayout) {
+        self.status_layout = layout;
+    }
+
+    /// Updates the layout after the window has been resized.
+    ///
+    /// Moving the window to a display with another pixel density changes the
+    /// scale factor, so the font and the status bar are resized as well.
+    pub fn resize(&mut self, canvas: &Canvas<Window>) {
+        self.screen_size = canvas.window().drawable_size();
+        let scale_factor = get_scale_factor(canvas);
+        if scale_factor != self.scale_factor {
+            match (self.load_font)(scale_factor) {
+                Ok(font) => {
+                    self.font = font;
+                    self.text_cache.clear();
+                    self.overlay_cache.clear();
+                }
+                Err(err) => eprintln!("Could not load the font: {}", err),
+            }
+            self.scale_factor = scale_factor;
+            self.bar_height = scale(32, scale_factor);
         }
+        self.invalidate();
     }
 
-    /// Paints a level onto the screen.
-    pub fn paint(&mut self, canvas: &mut Canvas<Window>, level: &Level) {
-        self.selector.reset(level.extents());
+    /// Multiplies the zoom level by the given factor.
+    pub fn zoom(&mut self, factor: f64) {
+        self.zoom = (self.zoom * factor).clamp(Painter::MIN_ZOOM, Painter::MAX_ZOOM);
+        self.invalidate();
+    }
+
+    /// Restores the zoom level so that the level fits onto the screen.
+    pub fn reset_zoom(&mut self) {
+        self.zoom = 1.0;
+        self.invalidate();
+    }
 
-        // Draw a full-size image onto an off-screen buffer
+    /// Returns the position of the level drawn at the given point in screen
+    /// coordinates, if any.
+    pub fn position_at(&self, level: &Level, x: i32, y: i32) -> Option<Position> {
+        let rect = self.level_rect?;
         let fullsize = self.tileset().get_rendering_size(level.extents());
-        let creator = canvas.texture_creator();
-        let mut texture = creator
-            .create_texture_target(PixelFormatEnum::RGBA8888, fullsize.0, fullsize.1)
-            .expect("Could not get texture target for off-screen rendering");
+        let ratio = f64::from(rect.width()) / f64::from(fullsize.0);
+        let x = (f64::from(x) * self.scale_factor - f64::from(rect.x())) / ratio;
+        let y = (f64::from(y) * self.scale_factor - f64::from(rect.y())) / ratio;
+        let pos = self.tileset().get_position(level.grid(), x, y);
+        if level.is_inside(&pos) {
+            Some(pos)
+        } else {
+            None
+        }
+    }
+
+    /// Forgets the static layer so that it gets painted again with the best tileset.
+    fn invalidate(&mut self) {
+        self.background = None;
+        self.frame = None;
+        self.followed = None;
+    }
+
+    /// Paints a level onto the screen.
+    ///
+    /// If an animation is given, the last move is drawn in progress.
+    /// If lines of text are given, they are shown in a box over the level.
+    pub fn paint(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        level: &Level,
+        animation: Option<&Animation>,
+        info: &StatusInfo,
+        overlay: Option<&[String]>,
+    ) {
+        if self.background.is_none() {
+            self.reset(canvas, level);
+        }
 
+        // Composite the moving items over the static layer off-screen
+        let mut frame = self.frame.take().unwrap();
         canvas
-            .with_texture_canvas(&mut texture, |cv| {
-                self.paint_fullsize(cv, level);
+            .with_texture_canvas(&mut frame, |cv| {
+                self.paint_foreground(cv, level, animation);
             })
             .unwrap();
 
         // Copy onto the screen with appropriate scaling
-        let final_rect = self.get_centered_image_rect(self.get_scaled_rendering_size(&level));
+        let fullsize = self.tileset().get_rendering_size(level.extents());
+        let scaled_size = self.get_scaled_rendering_size(level);
+        self.follow_player(level, scaled_size);
+        let final_rect = self.get_image_rect(scaled_size);
+        self.level_rect = final_rect;
 
         canvas.clear();
         let original_rect = Some(Rect::new(0, 0, fullsize.0, fullsize.1));
-        canvas.copy(&texture, original_rect, final_rect).unwrap();
+        canvas.copy(&frame, original_rect, final_rect).unwrap();
+        self.frame = Some(frame);
 
-        self.paint_status_bar(canvas, &level);
+        self.paint_status_bar(canvas, level, info);
+        if let Some(lines) = overlay {
+            self.paint_overlay(canvas, lines);
+        }
 
         canvas.present();
     }
 
-    /// Paints a full-size image of the given level onto the current render target.
-    fn paint_fullsize(&mut self, canvas: &mut Canvas<Window>, level: &Level) {
-        let (cols, rows) = level.extents();
+    /// Creates an off-screen texture of the given size.
+    fn create_fullsize_texture(&self, size: (u32, u32)) -> Texture<'a> {
+        self.creator
+            .create_texture_target(PixelFormatEnum::RGBA8888, size.0, size.1)
+            .expect("Could not get texture target for off-screen rendering")
+    }
+
+    /// Paints the parts of the level that never change (floor, shadows and walls)
+    /// onto the current render target.
+    fn paint_background(&mut self, canvas: &mut Canvas<Window>, level: &Level) {
         canvas.set_draw_color(Color::RGB(0, 0, 0));
         canvas.clear();
 
-        for r in 0..rows {
-            for c in 0..cols {
-                let pos = Position::new(r, c);
-                let (x, y) = self.tileset().get_coordinates(&pos);
+        for pos in level.positions() {
+            let (x, y) = self.tileset().get_coordinates(level.grid(), &pos);
 
-                // First paint the floor tiles
-                if level.is_square(&pos) {
-                    self.paint_tile(canvas, Tile::Square, x, y);
-                } else {
-                    self.paint_tile(canvas, Tile::Floor, x, y);
-                }
+            // First paint the floor tiles
+            if level.is_square(&pos) {
+                let color = level.square_color(&pos);
+                self.paint_colored_tile(canvas, Tile::Square, color, x, y);
+            } else if let Some(floor) = level.floor(&pos) {
+                self.paint_special_floor(canvas, floor, x, y);
+            } else {
+                self.paint_tile(canvas, Tile::Floor, x, y);
+            }
 
-                // Add the shadows
-                let flags = get_shadow_flags(&level, &pos);
-                for f in &[
-                    ShadowFlags::N_EDGE,
-                    ShadowFlags::S_EDGE,
-                    ShadowFlags::E_EDGE,
-                    ShadowFlags::W_EDGE,
-                    ShadowFlags::NE_CORNER,
-                    ShadowFlags::NW_CORNER,
-                    ShadowFlags::SE_CORNER,
-                    ShadowFlags::SW_CORNER,
-                ] {
-                    if flags.contains(*f) {
-                        self.paint_tile(canvas, Tile::Shadow(*f), x, y);
-                    }
+            // Add the shadows, which walls only cast up, down, left and right
+            let flags = match self.tileset().style() {
+                Style::Perspective if level.grid().directions().contains(&Direction::Up) => {
+                    get_shadow_flags(level, &pos)
+                }
+                _ => ShadowFlags::empty(),
+            };
+            for f in &[
+                ShadowFlags::N_EDGE,
+                ShadowFlags::S_EDGE,
+                ShadowFlags::E_EDGE,
+                ShadowFlags::W_EDGE,
+                ShadowFlags::NE_CORNER,
+                ShadowFlags::NW_CORNER,
+                ShadowFlags::SE_CORNER,
+                ShadowFlags::SW_CORNER,
+            ] {
+                if flags.contains(*f) {
+                    self.paint_tile(canvas, Tile::Shadow(*f), x, y);
                 }
+            }
 
-                // Draw the other items
+            // Draw the walls
+            if level.is_wall(&pos) {
                 let z = y - self.tileset().offset();
-                if level.is_wall(&pos) {
-                    self.paint_tile(canvas, Tile::Wall, x, z);
+                self.paint_tile(canvas, Tile::Wall, x, z);
+            }
+        }
+    }
+
+    /// Paints the boxes and the player over the static layer onto the current render target.
+    fn paint_foreground(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        level: &Level,
+        animation: Option<&Animation>,
+    ) {
+        canvas
+            .copy(self.background.as_ref().unwrap(), None, None)
+            .unwrap();
+
+        // Find out where the moving items come from
+        let player = level.player();
+        let mut origins = HashMap::new();
+        if let Some(anim) = animation {
+            let mv = anim.movement();
+            // A player taken away by a teleport pad is not seen moving
+            if level.grid().neighbor(&mv.origin(), mv.direction()) == player {
+                origins.insert(player, mv.origin());
+            }
+            if let Some((from, to)) = mv.box_motion() {
+                origins.insert(to, from);
+            }
+        }
+        let progress = animation.map_or(1.0, |anim| anim.progress());
+
+        let mut items: Vec<(Position, Tile)> =
+            level.boxes().map(|pos| (*pos, Tile::Rock)).collect();
+        items.extend(level.players().map(|pos| (*pos, Tile::Player)));
+        items.sort_by_key(|&(pos, _)| pos);
+
+        for (pos, tile) in items {
+            let (x, y) = match origins.get(&pos) {
+                Some(origin) => {
+                    self.get_interpolated_coordinates(level.grid(), origin, &pos, progress)
                 }
-                if level.is_box(&pos) {
-                    self.paint_tile(canvas, Tile::Rock, x, z);
+                None => self.tileset().get_coordinates(level.grid(), &pos),
+            };
+            if tile == Tile::Player && pos == player && level.player_count() > 1 {
+                self.paint_highlight(canvas, x, y);
+            }
+            let color = if tile == Tile::Rock {
+                level.box_color(&pos)
+            } else {
+                0
+            };
+            let y_item = y - self.tileset().offset();
+            self.paint_colored_tile(canvas, tile, color, x, y_item);
+
+            // Walls in the rows below are drawn in front of the item
+            if self.tileset().style() == Style::Flat {
+                continue;
+            }
+            for &dir in level.grid().directions() {
+                let mut below = level.grid().neighbor(&pos, dir);
+                if below.row() <= pos.row() {
+                    continue;
                 }
-                if level.is_player(&pos) {
-                    self.paint_tile(canvas, Tile::Player, x, z);
+                while level.is_wall(&below) {
+                    let (x, y) = self.tileset().get_coordinates(level.grid(), &below);
+                    self.paint_tile(canvas, Tile::Wall, x, y - self.tileset().offset());
+                    below = level.grid().neighbor(&below, dir);
                 }
             }
         }
     }
 
+    /// Paints a special floor, with the tile of the theme if it has one.
+    ///
+    /// Otherwise, the floor is marked on top of a plain floor tile.
+    fn paint_special_floor(&mut self, canvas: &mut Canvas<Window>, floor: Floor, x: i32, y: i32) {
+        let tile = match floor {
+            Floor::OneWay(dir) => Tile::OneWay(dir),
+            Floor::Ice => Tile::Ice,
+            Floor::Teleport => Tile::Teleport,
+        };
+        if self.tileset().location(tile).is_some() {
+            return self.paint_tile(canvas, tile, x, y);
+        }
+        self.paint_tile(canvas, Tile::Floor, x, y);
+
+        let tileset = self.tileset();
+        let (w, h) = (tileset.width() as i32, tileset.effective_height() as i32);
+        let top = y + (tileset.height() as i32 - h) / 2;
+        let (cx, cy) = (x + w / 2, top + h / 2);
+        let prev_color = canvas.draw_color();
+        let prev_blend_mode = canvas.blend_mode();
+        canvas.set_blend_mode(BlendMode::Blend);
+        match floor {
+            Floor::OneWay(dir) => {
+                let (dx, dy) = match dir {
+                    Direction::Up => (0, -1),
+                    Direction::Down => (0, 1),
+                    Direction::Left => (-1, 0),
+                    Direction::Right => (1, 0),
+                    Direction::UpLeft => (-1, -1),
+                    Direction::UpRight => (1, -1),
+                    Direction::DownLeft => (-1, 1),
+                    Direction::DownRight => (1, 1),
+                };
+                let r = w.min(h) / 4;
+                let tip = Point::new(cx + dx * r, cy + dy * r);
+                let tail = Point::new(cx - dx * r, cy - dy * r);
+                // The barbs of the arrow point backwards, on both sides of the shaft
+                let barb = |side: i32| {
+                    Point::new(
+                        cx + dx * r / 3 - dy * side * r / 2,
+                        cy + dy * r / 3 + dx * side * r / 2,
+                    )
+                };
+                canvas.set_draw_color(Color::RGBA(255, 255, 255, 192));
+                canvas
+                    .draw_lines(&[tail, tip, barb(1), tip, barb(-1)][..])
+                    .unwrap();
+            }
+            Floor::Ice => {
+                canvas.set_draw_color(Color::RGBA(160, 224, 255, 128));
+                canvas
+                    .fill_rect(Rect::new(x, top, w as u32, h as u32))
+                    .unwrap();
+            }
+            Floor::Teleport => {
+                canvas.set_draw_color(Color::RGBA(176, 64, 255, 160));
+                let rect = Rect::new(cx - w / 4, cy - h / 4, (w / 2) as u32, (h / 2) as u32);
+                canvas.fill_rect(rect).unwrap();
+            }
+        }
+        canvas.set_draw_color(prev_color);
+        canvas.set_blend_mode(prev_blend_mode);
+    }
+
+    /// Marks the floor under the player being moved.
+    fn paint_highlight(&self, canvas: &mut Canvas<Window>, x: i32, y: i32) {
+        let tileset = self.tileset();
+        let top = (tileset.height() - tileset.effective_height()) as i32 / 2;
+        let rect = Rect::new(x, y + top, tileset.width(), tileset.effective_height());
+
+        let prev_color = canvas.draw_color();
+        let prev_blend_mode = canvas.blend_mode();
+        let (r, g, b) = self.bar_text_color.rgb();
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(r, g, b, 96));
+        canvas.fill_rect(rect).unwrap();
+        canvas.set_draw_color(prev_color);
+        canvas.set_blend_mode(prev_blend_mode);
+    }
+
+    /// Returns the coordinates of an item moving between two positions.
+    fn get_interpolated_coordinates(
+        &self,
+        grid: &dyn Grid,
+        from: &Position,
+        to: &Position,
+        progress: f64,
+    ) -> (i32, i32) {
+        let (x0, y0) = self.tileset().get_coordinates(grid, from);
+        let (x1, y1) = self.tileset().get_coordinates(grid, to);
+        let lerp = |a: i32, b: i32| a + (progress * f64::from(b - a)).round() as i32;
+        (lerp(x0, x1), lerp(y0, y1))
+    }
+
     /// Paints the status bar
-    fn paint_status_bar(&mut self, canvas: &mut Canvas<Window>, level: &Level) {
+    fn paint_status_bar(&mut self, canvas: &mut Canvas<Window>, level: &Level, info: &StatusInfo) {
         let prev_color = canvas.draw_color();
         canvas.set_draw_color(self.bar_color);
         let rect = Rect::new(
             0,
-            (self.screen_size.1 - self.bar_height) as i32,
+            self.screen_size.1 as i32 - self.bar_height as i32,
             self.screen_size.0,
             self.bar_height,
         );
         canvas.fill_rect(rect).unwrap();
         canvas.set_draw_color(prev_color);
 
-        // Paints the number of moves
-        let s = format!("# moves: {}", level.get_steps());
-        self.paint_status_text(canvas, &s, StatusBarLocation::FlushLeft);
-
-        // Paints the level's title
-        self.paint_status_text(canvas, level.title(), StatusBarLocation::FlushRight);
+        let layout = self.status_layout.clone();
+        let groups = [
+            (StatusBarLocation::FlushLeft, &layout.left),
+            (StatusBarLocation::Center, &layout.center),
+            (StatusBarLocation::FlushRight, &layout.right),
+        ];
+        for &(location, fields) in &groups {
+            let texts: Vec<String> = fields
+                .iter()
+                .map(|f| f.text(level, info))
+                .filter(|text| !text.is_empty())
+                .collect();
+            self.paint_status_texts(canvas, &texts, location);
+        }
     }
 
-    /// Paints text in the status bar
-    fn paint_status_text(
+    /// Paints a group of texts side by side in the status bar
+    fn paint_status_texts(
         &mut self,
         canvas: &mut Canvas<Window>,
-        text: &str,
+        texts: &[String],
         location: StatusBarLocation,
     ) {
-        let surface = self.font.render(text).blended(self.bar_text_color).unwrap();
-        let creator = canvas.texture_creator();
-        let texture = creator.create_texture_from_surface(&surface).unwrap();
-        let margin = 4;
-        let (w, h) = {
-            let q = texture.query();
-            (q.width, q.height)
-        };
-        let (x, y) = match location {
-            StatusBarLocation::FlushLeft => {
-                (margin as i32, (self.screen_size.1 - margin - h) as i32)
+        if texts.is_empty() {
+            return;
+        }
+
+        let color = self.bar_text_color;
+        let mut sizes = Vec::with_capacity(texts.len());
+        for (index, text) in texts.iter().enumerate() {
+            let slot = (location, index);
+            let is_stale = match self.text_cache.get(&slot) {
+                Some(cached) => cached.text != *text || cached.color != color,
+                None => true,
+            };
+            if is_stale {
+                let surface = self.font.render(text).blended(color).unwrap();
+                let texture = self.creator.create_texture_from_surface(&surface).unwrap();
+                let cached = CachedText {
+                    text: text.clone(),
+                    color,
+                    texture,
+                };
+                self.text_cache.insert(slot, cached);
+            }
+            let q = self.text_cache[&slot].texture.query();
+            sizes.push((q.width, q.height));
+        }
+
+        let margin = scale(4, self.scale_factor);
+        let spacing = scale(24, self.scale_factor);
+        let total_width =
+            sizes.iter().map(|&(w, _)| w).sum::<u32>() + spacing * (sizes.len() as u32 - 1);
+        let mut x = match location {
+            StatusBarLocation::FlushLeft => margin as i32,
+            StatusBarLocation::Center => (self.screen_size.0 as i32 - total_width as i32) / 2,
+            StatusBarLocation::FlushRight => {
+                self.screen_size.0 as i32 - (margin + total_width) as i32
             }
-            StatusBarLocation::FlushRight => (
-                (self.screen_size.0 - margin - w) as i32,
-                (self.screen_size.1 - margin - h) as i32,
-            ),
         };
-        canvas
-            .copy(&texture, None, Some(Rect::new(x, y, w, h)))
-            .unwrap();
+        for (index, &(w, h)) in sizes.iter().enumerate() {
+            let y = self.screen_size.1 as i32 - (margin + h) as i32;
+            let texture = &self.text_cache[&(location, index)].texture;
+            canvas
+                .copy(texture, None, Some(Rect::new(x, y, w, h)))
+                .unwrap();
+            x += (w + spacing) as i32;
+        }
+    }
+
+    /// Paints lines of text centered in a translucent box over the level
+    fn paint_overlay(&mut self, canvas: &mut Canvas<Window>, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
+
+        let color = self.bar_text_color;
+        let is_stale = self.overlay_cache.len() != lines.len()
+            || self
+                .overlay_cache
+                .iter()
+                .zip(lines)
+                .any(|(cached, line)| cached.text != *line || cached.color != color);
+        if is_stale {
+            self.overlay_cache = lines
+                .iter()
+                .map(|line| {
+                    let surface = self.font.render(line).blended(color).unwrap();
+                    let texture = self.creator.create_texture_from_surface(&surface).unwrap();
+                    CachedText {
+                        text: line.clone(),
+                        color,
+                        texture,
+                    }
+                })
+                .collect();
+        }
+        let sizes: Vec<(u32, u32)> = self
+            .overlay_cache
+            .iter()
+            .map(|cached| {
+                let q = cached.texture.query();
+                (q.width, q.height)
+            })
+            .collect();
+
+        let padding = scale(16, self.scale_factor);
+        let spacing = scale(8, self.scale_factor);
+        let width = sizes.iter().map(|&(w, _)| w).max().unwrap_or(0) + 2 * padding;
+        let height = sizes.iter().map(|&(_, h)| h + spacing).sum::<u32>() - spacing + 2 * padding;
+        let view_size = self.get_view_size();
+        let x = (view_size.0 as i32 - width as i32) / 2;
+        let mut y = (view_size.1 as i32 - height as i32) / 2;
+
+        let prev_color = canvas.draw_color();
+        let prev_blend_mode = canvas.blend_mode();
+        let (r, g, b) = self.bar_color.rgb();
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(r, g, b, 224));
+        canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+        canvas.set_draw_color(prev_color);
+        canvas.set_blend_mode(prev_blend_mode);
+
+        y += padding as i32;
+        for (cached, &(w, h)) in self.overlay_cache.iter().zip(&sizes) {
+            let x = (view_size.0 as i32 - w as i32) / 2;
+            canvas
+                .copy(&cached.texture, None, Some(Rect::new(x, y, w, h)))
+                .unwrap();
+            y += (h + spacing) as i32;
+        }
     }
 
     /// Paints a tile at the given coordinates.
@@ -212,31 +675,139 @@ impl<'a> Painter<'a> {
             .unwrap();
     }
 
+    /// Paints a tile tinted with the colour of a box or square, where 0 stands for plain.
+    fn paint_colored_tile(
+        &mut self,
+        canvas: &mut Canvas<Window>,
+        tile: Tile,
+        color: u8,
+        x: i32,
+        y: i32,
+    ) {
+        if color == 0 {
+            return self.paint_tile(canvas, tile, x, y);
+        }
+        let (r, g, b) = PALETTE[usize::from(color - 1)];
+        self.selector
+            .select_mut()
+            .texture_mut()
+            .set_color_mod(r, g, b);
+        self.paint_tile(canvas, tile, x, y);
+        self.selector
+            .select_mut()
+            .texture_mut()
+            .set_color_mod(255, 255, 255);
+    }
+
     /// Returns the size of the drawing scaled to fit onto the screen.
     fn get_scaled_rendering_size(&self, level: &Level) -> (u32, u32) {
         let render_size = self.tileset().get_rendering_size(level.extents());
-        let width_ratio = f64::from(self.screen_size.0) / f64::from(render_size.0);
-        let h = self.screen_size.1 - self.bar_height;
-        let height_ratio = f64::from(h) / f64::from(render_size.1);
-        let ratio = f64::min(1.0, f64::min(width_ratio, height_ratio));
+        let ratio = self.get_ratio(self.tileset(), level);
 
         let scale = |sz: u32| (ratio * f64::from(sz)).floor() as u32;
 
         (scale(render_size.0), scale(render_size.1))
     }
 
-    /// Returns the Rect of an image of given dimensions so that it's centered on the screen.
-    fn get_centered_image_rect(&self, img_size: (u32, u32)) -> Option<Rect> {
-        let x = (self.screen_size.0 - img_size.0) as i32 / 2;
-        let y = (self.screen_size.1 - self.bar_height - img_size.1) as i32 / 2;
+    /// Returns the ratio between the size on the screen and the full size
+    /// of a level drawn with the given tileset.
+    fn get_ratio(&self, tileset: &Tileset, level: &Level) -> f64 {
+        let render_size = tileset.get_rendering_size(level.extents());
+        let width_ratio = f64::from(self.screen_size.0) / f64::from(render_size.0);
+        let h = self.screen_size.1.saturating_sub(self.bar_height);
+        let height_ratio = f64::from(h) / f64::from(render_size.1);
+        let ratio = f64::min(width_ratio, height_ratio) * self.zoom;
+        match self.min_tile_size {
+            Some(size) => {
+                let min_width = f64::from(scale(size, self.scale_factor));
+                f64::max(ratio, min_width / f64::from(tileset.width()))
+            }
+            None => ratio,
+        }
+    }
+
+    /// Returns the Rect of an image of given dimensions on the screen.
+    ///
+    /// The image is centered along the dimensions in which it fits onto the screen,
+    /// and positioned according to the camera along the others.
+    fn get_image_rect(&mut self, img_size: (u32, u32)) -> Option<Rect> {
+        let view_size = self.get_view_size();
+        let place = |img: u32, view: u32, camera: &mut i32| {
+            if img <= view {
+                (view as i32 - img as i32) / 2
+            } else {
+                *camera = (*camera).clamp(0, (img - view) as i32);
+                -*camera
+            }
+        };
+        let x = place(img_size.0, view_size.0, &mut self.camera.0);
+        let y = place(img_size.1, view_size.1, &mut self.camera.1);
         Some(Rect::new(x, y, img_size.0, img_size.1))
     }
 
-    fn tileset(&self) -> &Tileset {
+    /// Returns the size of the part of the screen where the level is drawn.
+    fn get_view_size(&self) -> (u32, u32) {
+        (
+            self.screen_size.0,
+            self.screen_size.1.saturating_sub(self.bar_height),
+        )
+    }
+
+    /// Moves the camera so that the player stays away from the edges of the screen.
+    fn follow_player(&mut self, level: &Level, scaled_size: (u32, u32)) {
+        let player = level.player();
+        if self.followed == Some(player) {
+            return;
+        }
+        self.followed = Some(player);
+
+        let fullsize = self.tileset().get_rendering_size(level.extents());
+        let ratio = f64::from(scaled_size.0) / f64::from(fullsize.0);
+        let (x, y) = self.tileset().get_coordinates(level.grid(), &player);
+        let tile_size = (
+            f64::from(self.tileset().width()) * ratio,
+            f64::from(self.tileset().effective_height()) * ratio,
+        );
+        let view_size = self.get_view_size();
+
+        let follow = |pos: i32, tile: f64, view: u32, camera: &mut i32| {
+            let view = f64::from(view);
+            let pos = f64::from(pos) * ratio;
+            let margin = f64::min(Painter::SCROLL_MARGIN * tile, (view - tile) / 2.0).max(0.0);
+            let cam = f64::from(*camera);
+            if pos - margin < cam {
+                *camera = (pos - margin).floor() as i32;
+            } else if pos + tile + margin > cam + view {
+                *camera = (pos + tile + margin - view).ceil() as i32;
+            }
+        };
+        follow(x, tile_size.0, view_size.0, &mut self.camera.0);
+        follow(y, tile_size.1, view_size.1, &mut self.camera.1);
+    }
+
+    fn tileset(&self) -> &Tileset<'a> {
         self.selector.select()
     }
 }
 
+/// Returns the number of pixels per screen coordinate of the given canvas.
+///
+/// This is greater than 1.0 on high-DPI displays.
+fn get_scale_factor(canvas: &Canvas<Window>) -> f64 {
+    let (drawable_width, _) = canvas.window().drawable_size();
+    let (window_width, _) = canvas.window().size();
+    if window_width > 0 {
+        f64::max(1.0, f64::from(drawable_width) / f64::from(window_width))
+    } else {
+        1.0
+    }
+}
+
+/// Scales a length given in screen coordinates to pixels.
+fn scale(length: u32, scale_factor: f64) -> u32 {
+    (f64::from(length) * scale_factor).round() as u32
+}
+
 /// Returns the shadow flags for a particular position in the given level.
 fn get_shadow_flags(level: &Level, pos: &Position) -> ShadowFlags {
     let north = pos.neighbor(Direction::Up);
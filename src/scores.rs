@@ -0,0 +1,123 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Keeps the best scores of the player.
+//!
+//! Scores are stored in `sokoban-rs/scores.toml` in the user's data directory.
+
+use dirs;
+use error::SokobanError;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use toml;
+
+/// Represents the score obtained when solving a level.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    /// The number of moves
+    pub moves: i32,
+    /// The number of pushes
+    pub pushes: i32,
+    /// The time spent solving the level, in milliseconds
+    pub millis: u64,
+}
+
+impl Score {
+    /// Creates a new score.
+    pub fn new(moves: i32, pushes: i32, time: Duration) -> Score {
+        Score {
+            moves,
+            pushes,
+            millis: time.as_millis() as u64,
+        }
+    }
+
+    /// Returns true if this score is better than the other one.
+    ///
+    /// Scores are compared by number of moves, then by number of pushes,
+    /// then by time.
+    pub fn is_better_than(&self, other: &Score) -> bool {
+        (self.moves, self.pushes, self.millis) < (other.moves, other.pushes, other.millis)
+    }
+}
+
+/// Represents the best scores of the player.
+///
+/// The default scores are empty and are never written to disk.
+#[derive(Default)]
+pub struct Scores {
+    /// The file in which scores are stored
+    path: Option<PathBuf>,
+    /// The best score of each level
+    records: BTreeMap<String, Score>,
+}
+
+impl Scores {
+    /// Loads the best scores from the default location.
+    ///
+    /// No scores are loaded if the file does not exist.
+    pub fn load() -> Result<Scores, SokobanError> {
+        let path = dirs::data_dir().map(|dir| dir.join("sokoban-rs").join("scores.toml"));
+        let records = match path {
+            Some(ref path) if path.is_file() => toml::from_str(&fs::read_to_string(path)?)?,
+            _ => BTreeMap::new(),
+        };
+        Ok(Scores { path, records })
+    }
+
+    /// Returns the best score for a level of a collection.
+    pub fn best(&self, collection: &str, level: &str) -> Option<Score> {
+        self.records.get(&key(collection, level)).cloned()
+    }
+
+    /// Records a score for a level of a collection.
+    ///
+    /// Returns true if this is a new best score.
+    pub fn record(
+        &mut self,
+        collection: &str,
+        level: &str,
+        score: Score,
+    ) -> Result<bool, SokobanError> {
+        let is_best = match self.best(collection, level) {
+            Some(best) => score.is_better_than(&best),
+            None => true,
+        };
+        if is_best {
+            self.records.insert(key(collection, level), score);
+            self.save()?;
+        }
+        Ok(is_best)
+    }
+
+    /// Writes the best scores to disk.
+    fn save(&self) -> Result<(), SokobanError> {
+        if let Some(ref path) = self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let contents = toml::to_string(&self.records).expect("Could not serialize scores");
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the key under which the score of a level is stored.
+fn key(collection: &str, level: &str) -> String {
+    format!("{}/{}", collection, level)
+}
@@ -0,0 +1,316 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Searches for solutions and analyses the structure of levels.
+//!
+//! Only the player being moved is searched for. The other players stand in the way
+//! like walls, so levels that need several players to move are not solved.
+
+use game::{Direction, Level, Position};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Represents the outcome of a search for a solution.
+pub struct Search {
+    /// The moves that solve the level, if a solution was found within the budget
+    pub solution: Option<Vec<Direction>>,
+    /// The number of positions explored
+    pub explored: usize,
+}
+
+/// Represents a position of the search, reached from a previous one.
+///
+/// Only the positions waiting to be explored keep their level, so that the
+/// memory used by the search grows with the number of positions, not with
+/// the size of the level.
+struct Node {
+    /// The index of the previous position and the move made from it
+    parent: Option<(usize, Position, Direction)>,
+}
+
+/// Identifies a position of the search in the table of the positions already seen.
+///
+/// The table is keyed on the Zobrist hash of the level. The boxes and the players
+/// only tell positions apart when their hashes collide.
+#[derive(PartialEq, Eq)]
+struct StateKey {
+    /// The hash of the level
+    hash: u64,
+    /// The boxes and their colours, in order
+    boxes: Vec<(Position, u8)>,
+    /// The positions of the players
+    players: Vec<Position>,
+}
+
+impl Hash for StateKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+/// Searches for a solution with the fewest pushes, exploring at most `budget` positions.
+pub fn solve_pushes(level: &Level, budget: usize) -> Search {
+    if level.is_completed() {
+        return Search {
+            solution: Some(Vec::new()),
+            explored: 0,
+        };
+    }
+
+    let dead = dead_squares(level);
+    let mut nodes = vec![Node { parent: None }];
+    let mut frontier = VecDeque::new();
+    frontier.push_back((0, start_level(level)));
+    // Positions are told apart by the area of the player rather than their exact cell
+    let mut seen = HashSet::new();
+    seen.insert(state_key(level, level.canonical_player()));
+    let mut explored = 0;
+    while explored < budget {
+        let (current, position) = match frontier.pop_front() {
+            Some(entry) => entry,
+            None => break,
+        };
+        explored += 1;
+        let reachable = reachable_positions(&position);
+        let mut boxes: Vec<Position> = position.boxes().cloned().collect();
+        boxes.sort();
+        for b in boxes {
+            for &dir in level.grid().directions() {
+                let stand = level.grid().neighbor(&b, dir.opposite());
+                let target = level.grid().neighbor(&b, dir);
+                if !reachable.contains_key(&stand)
+                    || !position.is_free(&target)
+                    || dead.contains(&target)
+                {
+                    continue;
+                }
+                let mut next = position.clone();
+                next.set_player(stand);
+                if next.step(dir).is_none()
+                    || !seen.insert(state_key(&next, next.canonical_player()))
+                {
+                    continue;
+                }
+                nodes.push(Node {
+                    parent: Some((current, stand, dir)),
+                });
+                if next.is_completed() {
+                    return Search {
+                        solution: replay_pushes(level, &nodes),
+                        explored,
+                    };
+                }
+                next.forget_moves();
+                frontier.push_back((nodes.len() - 1, next));
+            }
+        }
+    }
+    Search {
+        solution: None,
+        explored,
+    }
+}
+
+/// Searches for a solution with the fewest moves, exploring at most `budget` positions.
+pub fn solve_moves(level: &Level, budget: usize) -> Search {
+    if level.is_completed() {
+        return Search {
+            solution: Some(Vec::new()),
+            explored: 0,
+        };
+    }
+
+    let dead = dead_squares(level);
+    let mut nodes = vec![Node { parent: None }];
+    let mut frontier = VecDeque::new();
+    frontier.push_back((0, start_level(level)));
+    let mut seen = HashSet::new();
+    seen.insert(state_key(level, level.player()));
+    let mut explored = 0;
+    while explored < budget {
+        let (current, position) = match frontier.pop_front() {
+            Some(entry) => entry,
+            None => break,
+        };
+        explored += 1;
+        for &dir in level.grid().directions() {
+            let player = position.player();
+            let next_to_player = level.grid().neighbor(&player, dir);
+            if position.is_box(&next_to_player)
+                && dead.contains(&level.grid().neighbor(&next_to_player, dir))
+            {
+                continue;
+            }
+            let mut next = position.clone();
+            if next.step(dir).is_none() || !seen.insert(state_key(&next, next.player())) {
+                continue;
+            }
+            nodes.push(Node {
+                parent: Some((current, player, dir)),
+            });
+            if next.is_completed() {
+                let mut solution = Vec::new();
+                let mut index = nodes.len() - 1;
+                while let Some((parent, _, dir)) = nodes[index].parent {
+                    solution.push(dir);
+                    index = parent;
+                }
+                solution.reverse();
+                return Search {
+                    solution: Some(solution),
+                    explored,
+                };
+            }
+            // The moves are kept in the nodes, not in the levels
+            next.forget_moves();
+            frontier.push_back((nodes.len() - 1, next));
+        }
+    }
+    Search {
+        solution: None,
+        explored,
+    }
+}
+
+/// Returns a copy of a level to start a search from, without the moves made so far.
+fn start_level(level: &Level) -> Level {
+    let mut level = level.clone();
+    level.forget_moves();
+    level
+}
+
+/// Returns the moves leading to the last position of a search by pushes,
+/// walking the player between the pushes.
+fn replay_pushes(level: &Level, nodes: &[Node]) -> Option<Vec<Direction>> {
+    let mut pushes = Vec::new();
+    let mut index = nodes.len() - 1;
+    while let Some((parent, stand, dir)) = nodes[index].parent {
+        pushes.push((stand, dir));
+        index = parent;
+    }
+
+    let mut level = level.clone();
+    let mut solution = Vec::new();
+    for &(stand, dir) in pushes.iter().rev() {
+        for step in find_path(&level, stand)? {
+            level.step(step)?;
+            solution.push(step);
+        }
+        level.step(dir)?;
+        solution.push(dir);
+    }
+    Some(solution)
+}
+
+/// Returns the key of a position of the search, where the player being moved
+/// stands at the given cell.
+fn state_key(level: &Level, player: Position) -> StateKey {
+    let mut boxes: Vec<(Position, u8)> = level
+        .boxes()
+        .map(|pos| (*pos, level.box_color(pos)))
+        .collect();
+    boxes.sort();
+    let mut players: Vec<Position> = level.players().cloned().collect();
+    players[level.active_player()] = player;
+    StateKey {
+        hash: level.state_hash(),
+        boxes,
+        players,
+    }
+}
+
+/// Returns the shortest walk of the player to the given position, without pushing any box.
+pub fn find_path(level: &Level, to: Position) -> Option<Vec<Direction>> {
+    let reachable = reachable_positions(level);
+    let mut path = Vec::new();
+    let mut pos = to;
+    while pos != level.player() {
+        let (previous, dir) = (*reachable.get(&pos)?)?;
+        path.push(dir);
+        pos = previous;
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Returns the positions the player can walk to, along with the position and the
+/// direction of the last step of the shortest walk to each of them.
+///
+/// The walk follows the rules of the special floors, which may take the player
+/// farther than the next cell.
+pub fn reachable_positions(level: &Level) -> HashMap<Position, Option<(Position, Direction)>> {
+    let mut reached = HashMap::new();
+    let mut queue = VecDeque::new();
+    reached.insert(level.player(), None);
+    queue.push_back(level.player());
+    while let Some(pos) = queue.pop_front() {
+        for &dir in level.grid().directions() {
+            if let Some(next) = level.walk_destination(&pos, dir) {
+                if let Entry::Vacant(entry) = reached.entry(next) {
+                    entry.insert(Some((pos, dir)));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    reached
+}
+
+/// Returns the positions the player could walk to if there were no boxes.
+pub fn floor_area(level: &Level) -> HashSet<Position> {
+    let mut area = HashSet::new();
+    let mut queue = VecDeque::new();
+    area.insert(level.player());
+    queue.push_back(level.player());
+    while let Some(pos) = queue.pop_front() {
+        for &dir in level.grid().directions() {
+            let next = level.grid().neighbor(&pos, dir);
+            if level.is_inside(&next) && !level.is_wall(&next) && area.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    area
+}
+
+/// Returns the positions of the floor from which a box can never be pushed
+/// onto a square, even if there were no other boxes.
+///
+/// The special floors are not taken into account, so none is reported in levels that have some.
+pub fn dead_squares(level: &Level) -> HashSet<Position> {
+    if level.has_special_floors() {
+        return HashSet::new();
+    }
+    let area = floor_area(level);
+
+    // Pull boxes away from the squares to find the live positions
+    let mut live: HashSet<Position> = level
+        .squares()
+        .filter(|pos| area.contains(pos))
+        .cloned()
+        .collect();
+    let mut queue: VecDeque<Position> = live.iter().cloned().collect();
+    while let Some(pos) = queue.pop_front() {
+        for &dir in level.grid().directions() {
+            let from = level.grid().neighbor(&pos, dir);
+            let stand = level.grid().neighbor(&from, dir);
+            if area.contains(&from) && area.contains(&stand) && live.insert(from) {
+                queue.push_back(from);
+            }
+        }
+    }
+    area.difference(&live).cloned().collect()
+}
@@ -0,0 +1,224 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use game::Level;
+use input::{Action, KeyBindings};
+use scores::Score;
+use std::time::{Duration, Instant};
+
+/// Represents a piece of information that can be shown in the status bar.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusField {
+    /// The number of moves
+    Moves,
+    /// The number of pushes
+    Pushes,
+    /// The time spent on the level
+    Time,
+    /// The index of the level in the collection
+    Level,
+    /// The title of the collection
+    Collection,
+    /// The title of the level
+    Title,
+    /// The number of boxes on a goal square
+    Boxes,
+    /// The best recorded score for the level
+    Best,
+    /// A message about the state of the game, such as the current tool of the editor
+    Message,
+}
+
+impl StatusField {
+    /// Returns the text of the field for the given level.
+    pub fn text(self, level: &Level, info: &StatusInfo) -> String {
+        match self {
+            StatusField::Moves => format!("# moves: {}", level.get_steps()),
+            StatusField::Pushes => format!("# pushes: {}", level.get_pushes()),
+            StatusField::Time => format_duration(info.elapsed),
+            StatusField::Level => format!("{} / {}", info.level_number, info.level_count),
+            StatusField::Collection => info.collection.to_owned(),
+            StatusField::Title => level.title().to_owned(),
+            StatusField::Boxes => format!(
+                "boxes: {} / {}",
+                level.boxes_on_squares(),
+                level.box_count()
+            ),
+            StatusField::Best => match info.best {
+                Some(best) => format!(
+                    "best: {} / {} / {}",
+                    best.moves,
+                    best.pushes,
+                    format_duration(Duration::from_millis(best.millis))
+                ),
+                None => String::from("best: -"),
+            },
+            StatusField::Message => info.message.to_owned(),
+        }
+    }
+}
+
+/// Represents the fields shown in each part of the status bar.
+#[derive(Clone, Debug, Default)]
+pub struct StatusLayout {
+    /// The fields shown from the left edge of the status bar
+    pub left: Vec<StatusField>,
+    /// The fields shown in the middle of the status bar
+    pub center: Vec<StatusField>,
+    /// The fields shown from the right edge of the status bar
+    pub right: Vec<StatusField>,
+}
+
+/// Represents the information about the game that does not belong to the level itself.
+pub struct StatusInfo<'a> {
+    /// The number of the current level, starting from 1
+    pub level_number: usize,
+    /// The number of levels in the collection
+    pub level_count: usize,
+    /// The title of the collection
+    pub collection: &'a str,
+    /// The time spent on the current level
+    pub elapsed: Duration,
+    /// The best recorded score for the current level
+    pub best: Option<Score>,
+    /// A message about the state of the game
+    pub message: &'a str,
+}
+
+/// Measures the time spent on a level.
+///
+/// The stopwatch starts with the first move and stops when the level is completed.
+#[derive(Default)]
+pub struct Stopwatch {
+    /// The time at which the stopwatch started
+    start: Option<Instant>,
+    /// The time measured when the stopwatch stopped
+    stopped: Option<Duration>,
+}
+
+impl Stopwatch {
+    /// Starts the stopwatch unless it has already been started.
+    pub fn start(&mut self) {
+        if self.start.is_none() {
+            self.start = Some(Instant::now());
+        }
+    }
+
+    /// Stops the stopwatch, freezing the elapsed time.
+    pub fn stop(&mut self) {
+        if self.stopped.is_none() {
+            self.stopped = Some(self.elapsed());
+        }
+    }
+
+    /// Sets the elapsed time back to zero.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.stopped = None;
+    }
+
+    /// Returns true if the stopwatch has been started and not stopped.
+    pub fn is_running(&self) -> bool {
+        self.start.is_some() && self.stopped.is_none()
+    }
+
+    /// Returns the time measured by the stopwatch.
+    pub fn elapsed(&self) -> Duration {
+        match (self.stopped, self.start) {
+            (Some(stopped), _) => stopped,
+            (None, Some(start)) => start.elapsed(),
+            (None, None) => Duration::from_secs(0),
+        }
+    }
+}
+
+/// Returns the lines of text shown when a level has been completed.
+///
+/// The best score is the one recorded before this completion.
+pub fn completion_lines(
+    score: Score,
+    is_best: bool,
+    best: Option<Score>,
+    bindings: &KeyBindings,
+) -> Vec<String> {
+    let mut lines = vec![
+        String::from("Level completed!"),
+        format!(
+            "moves: {}    pushes: {}    time: {}",
+            score.moves,
+            score.pushes,
+            format_duration(Duration::from_millis(score.millis))
+        ),
+    ];
+    match best {
+        _ if is_best => lines.push(String::from("New personal best!")),
+        Some(best) => lines.push(format!(
+            "best: {} moves, {} pushes, {}",
+            best.moves,
+            best.pushes,
+            format_duration(Duration::from_millis(best.millis))
+        )),
+        None => {}
+    }
+    lines.push(format!(
+        "{}: continue    {}: replay    {}: retry",
+        key_name(bindings, Action::Continue),
+        key_name(bindings, Action::Replay),
+        key_name(bindings, Action::Restart)
+    ));
+    lines
+}
+
+/// Returns the lines of text shown when the end of a collection has been reached.
+///
+/// The scores are those obtained during this session, one per level.
+pub fn summary_lines(
+    collection: &str,
+    scores: &[Option<Score>],
+    bindings: &KeyBindings,
+) -> Vec<String> {
+    let solved: Vec<&Score> = scores.iter().filter_map(|score| score.as_ref()).collect();
+    let moves: i32 = solved.iter().map(|score| score.moves).sum();
+    let pushes: i32 = solved.iter().map(|score| score.pushes).sum();
+    let millis: u64 = solved.iter().map(|score| score.millis).sum();
+    vec![
+        format!("End of {}", collection),
+        format!("levels solved: {} / {}", solved.len(), scores.len()),
+        format!(
+            "moves: {}    pushes: {}    time: {}",
+            moves,
+            pushes,
+            format_duration(Duration::from_millis(millis))
+        ),
+        format!(
+            "{}: quit    {}: back to the last level",
+            key_name(bindings, Action::Continue),
+            key_name(bindings, Action::PreviousLevel)
+        ),
+    ]
+}
+
+/// Returns the name of a key bound to an action, or the name of the action
+/// if no key is bound to it.
+fn key_name(bindings: &KeyBindings, action: Action) -> &str {
+    bindings.key_name(action).unwrap_or_else(|| action.name())
+}
+
+/// Formats a duration as minutes, seconds and tenths of a second.
+pub fn format_duration(duration: Duration) -> String {
+    let tenths = duration.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, (tenths / 10) % 60, tenths % 10)
+}
@@ -0,0 +1,150 @@
+// This file is part of sokoban-rs
+// Copyright 2015 Sébastien Watteau
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reads theme manifests.
+//!
+//! A theme is a TOML file that describes one or more tilesets:
+//!
+//! ```toml
+//! name = "My theme"
+//!
+//! [[tilesets]]
+//! image = "image/my-tileset.png"
+//! width = 101
+//! height = 171
+//! effective_height = 83
+//! offset = 40
+//!
+//! [tilesets.tiles]
+//! floor = [0, 0]
+//! wall = [0, 2]
+//! # ...
+//! ```
+//!
+//! The location of each tile is given as a column and a row in the image.
+//!
+//! Setting `style = "flat"` at the top of the manifest draws the tiles
+//! top-down, without stacking them and without shadows. The effective height
+//! then defaults to the height of the tiles and the offset to zero.
+
+use assets::{self, Asset};
+use error::SokobanError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tileset::{InvalidTileset, Style, Tile};
+use toml;
+
+/// Represents a theme.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    /// The name of the theme
+    pub name: String,
+    /// The way tiles are laid out
+    #[serde(default)]
+    pub style: Style,
+    /// The tilesets of the theme
+    pub tilesets: Vec<TilesetDescriptor>,
+}
+
+/// Describes a tileset.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TilesetDescriptor {
+    /// The path to the tileset image, relative to the manifest or to the assets directory
+    image: PathBuf,
+    /// The width of a tile
+    pub width: u32,
+    /// The height of a tile
+    pub height: u32,
+    /// The effective height of a tile (used for stacking)
+    effective_height: Option<u32>,
+    /// The offset needed to draw items on the floor
+    offset: Option<i32>,
+    /// The column and row of each tile in the image
+    tiles: HashMap<String, (u32, u32)>,
+    /// The directory of the manifest, if it was read from a file
+    #[serde(skip)]
+    dir: Option<PathBuf>,
+}
+
+impl Theme {
+    /// Loads a theme given its name or the path to its manifest.
+    ///
+    /// Themes given by name are looked up as `themes/<name>.toml`
+    /// in the assets directories.
+    pub fn load(name: &str) -> Result<Theme, SokobanError> {
+        let path = Path::new(name);
+        let (contents, manifest) = if path.is_file() {
+            (fs::read_to_string(path)?, Some(path.to_path_buf()))
+        } else {
+            match assets::locate(format!("themes/{}.toml", name))? {
+                Asset::File(path) => (fs::read_to_string(&path)?, Some(path)),
+                Asset::Embedded(bytes) => (String::from_utf8_lossy(bytes).into_owned(), None),
+            }
+        };
+        let mut theme: Theme = toml::from_str(&contents)?;
+        if theme.tilesets.is_empty() {
+            return Err(InvalidTileset::NoTileset(theme.name).into());
+        }
+        let dir = manifest.as_ref().and_then(|path| path.parent());
+        for tileset in &mut theme.tilesets {
+            tileset.dir = dir.map(Path::to_path_buf);
+        }
+        Ok(theme)
+    }
+}
+
+impl TilesetDescriptor {
+    /// Locates the tileset image.
+    ///
+    /// A relative path is looked up next to the manifest first, then in the assets directories.
+    pub fn image(&self) -> Result<Asset, SokobanError> {
+        if let Some(ref dir) = self.dir {
+            let path = dir.join(&self.image);
+            if path.is_file() {
+                return Ok(Asset::File(path));
+            }
+        }
+        assets::locate(&self.image)
+    }
+
+    /// Returns the effective height of a tile, which defaults to its height.
+    pub fn effective_height(&self) -> u32 {
+        self.effective_height.unwrap_or(self.height)
+    }
+
+    /// Returns the offset needed to draw items on the floor, which defaults to zero.
+    pub fn offset(&self) -> i32 {
+        self.offset.unwrap_or(0)
+    }
+
+    /// Returns the location of each tile in the image.
+    ///
+    /// All the tiles needed to draw a level in the given style must be present.
+    pub fn locations(&self, style: Style) -> Result<HashMap<Tile, (u32, u32)>, SokobanError> {
+        let mut locations = HashMap::new();
+        for (name, &location) in &self.tiles {
+            let tile = name.parse::<Tile>()?;
+            locations.insert(tile, location);
+        }
+        let mut required = Tile::required(style).into_iter();
+        if let Some(tile) = required.find(|t| !locations.contains_key(t)) {
+            return Err(InvalidTileset::MissingTile(tile.name()).into());
+        }
+        Ok(locations)
+    }
+}
@@ -13,14 +13,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use game::Position;
+use game::{Direction, Position};
+use grid::Grid;
 use sdl2::rect::Rect;
 use sdl2::render::Texture;
 use shadow::ShadowFlags;
 use std::cmp;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 
 /// Represents a kind of tile.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Tile {
     /// Standard floor tile
     Floor,
@@ -34,6 +38,134 @@ pub enum Tile {
     Player,
     /// Shadow tile
     Shadow(ShadowFlags),
+    /// One-way floor tile
+    OneWay(Direction),
+    /// Ice floor tile
+    Ice,
+    /// Teleport pad tile
+    Teleport,
+}
+
+impl Tile {
+    /// Returns all the kinds of tiles.
+    pub fn all() -> Vec<Tile> {
+        let mut tiles = Tile::required(Style::Flat);
+        tiles.extend(Tile::shadows());
+        tiles.extend(Tile::special_floors());
+        tiles
+    }
+
+    /// Returns the tiles a tileset of the given style must provide.
+    pub fn required(style: Style) -> Vec<Tile> {
+        let mut tiles = vec![
+            Tile::Floor,
+            Tile::Wall,
+            Tile::Rock,
+            Tile::Square,
+            Tile::Player,
+        ];
+        if style == Style::Perspective {
+            tiles.extend(Tile::shadows());
+        }
+        tiles
+    }
+
+    /// Returns the tiles of the special floors, which tilesets may leave out.
+    fn special_floors() -> Vec<Tile> {
+        vec![
+            Tile::OneWay(Direction::Up),
+            Tile::OneWay(Direction::Down),
+            Tile::OneWay(Direction::Left),
+            Tile::OneWay(Direction::Right),
+            Tile::Ice,
+            Tile::Teleport,
+        ]
+    }
+
+    /// Returns the shadow tiles.
+    fn shadows() -> Vec<Tile> {
+        vec![
+            Tile::Shadow(ShadowFlags::N_EDGE),
+            Tile::Shadow(ShadowFlags::S_EDGE),
+            Tile::Shadow(ShadowFlags::E_EDGE),
+            Tile::Shadow(ShadowFlags::W_EDGE),
+            Tile::Shadow(ShadowFlags::NE_CORNER),
+            Tile::Shadow(ShadowFlags::NW_CORNER),
+            Tile::Shadow(ShadowFlags::SE_CORNER),
+            Tile::Shadow(ShadowFlags::SW_CORNER),
+        ]
+    }
+
+    /// Returns the name of the tile in theme manifests.
+    pub fn name(self) -> String {
+        let name = match self {
+            Tile::Floor => "floor",
+            Tile::Wall => "wall",
+            Tile::Rock => "rock",
+            Tile::Square => "square",
+            Tile::Player => "player",
+            Tile::Shadow(ShadowFlags::N_EDGE) => "shadow_n",
+            Tile::Shadow(ShadowFlags::S_EDGE) => "shadow_s",
+            Tile::Shadow(ShadowFlags::E_EDGE) => "shadow_e",
+            Tile::Shadow(ShadowFlags::W_EDGE) => "shadow_w",
+            Tile::Shadow(ShadowFlags::NE_CORNER) => "shadow_ne",
+            Tile::Shadow(ShadowFlags::NW_CORNER) => "shadow_nw",
+            Tile::Shadow(ShadowFlags::SE_CORNER) => "shadow_se",
+            Tile::Shadow(ShadowFlags::SW_CORNER) => "shadow_sw",
+            Tile::Shadow(ShadowFlags { .. }) => "shadow",
+            Tile::OneWay(Direction::Up) => "one_way_up",
+            Tile::OneWay(Direction::Down) => "one_way_down",
+            Tile::OneWay(Direction::Left) => "one_way_left",
+            Tile::OneWay(Direction::Right) => "one_way_right",
+            Tile::OneWay(_) => "one_way",
+            Tile::Ice => "ice",
+            Tile::Teleport => "teleport",
+        };
+        name.to_owned()
+    }
+}
+
+impl FromStr for Tile {
+    type Err = InvalidTileset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Tile::all()
+            .into_iter()
+            .find(|tile| tile.name() == s)
+            .ok_or_else(|| InvalidTileset::UnknownTile(s.to_owned()))
+    }
+}
+
+/// Represents the way tiles are laid out.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Style {
+    /// Pseudo-3D tiles stacked on top of each other, with shadows
+    #[default]
+    Perspective,
+    /// Flat top-down tiles
+    Flat,
+}
+
+/// Represents an error due to an invalid tileset description.
+#[derive(Debug)]
+pub enum InvalidTileset {
+    /// The theme has no tileset
+    NoTileset(String),
+    /// The tile name is unknown
+    UnknownTile(String),
+    /// A tile has no location
+    MissingTile(String),
+}
+
+impl Display for InvalidTileset {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            InvalidTileset::NoTileset(ref name) => write!(f, "theme `{}' has no tileset", name),
+            InvalidTileset::UnknownTile(ref name) => write!(f, "unknown tile `{}'", name),
+            InvalidTileset::MissingTile(ref name) => write!(f, "missing tile `{}'", name),
+        }
+    }
 }
 
 pub struct Tileset<'a> {
@@ -42,6 +174,8 @@ pub struct Tileset<'a> {
     height: u32,
     effective_height: u32,
     offset: i32,
+    style: Style,
+    locations: HashMap<Tile, (u32, u32)>,
 }
 
 impl<'a> Tileset<'a> {
@@ -51,6 +185,8 @@ impl<'a> Tileset<'a> {
         height: u32,
         effective_height: u32,
         offset: i32,
+        style: Style,
+        locations: HashMap<Tile, (u32, u32)>,
     ) -> Tileset<'a> {
         Tileset {
             texture,
@@ -58,14 +194,21 @@ impl<'a> Tileset<'a> {
             height,
             effective_height,
             offset,
+            style,
+            locations,
         }
     }
 
     /// Returns the associated texture
-    pub fn texture(&self) -> &Texture {
+    pub fn texture(&self) -> &Texture<'a> {
         &self.texture
     }
 
+    /// Returns the associated texture, for changing how it is drawn
+    pub fn texture_mut(&mut self) -> &mut Texture<'a> {
+        &mut self.texture
+    }
+
     /// Returns the width of a tile.
     pub fn width(&self) -> u32 {
         self.width
@@ -86,34 +229,34 @@ impl<'a> Tileset<'a> {
         self.offset
     }
 
+    /// Returns the style of the tiles.
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
     /// Returns the location of the tile in the tileset texture.
     pub fn location(&self, tile: Tile) -> Option<(u32, u32)> {
-        match tile {
-            Tile::Floor => Some((0, 0)),
-            Tile::Wall => Some((0, 2)),
-            Tile::Rock => Some((2, 0)),
-            Tile::Square => Some((1, 0)),
-            Tile::Player => Some((3, 0)),
-            Tile::Shadow(ShadowFlags::N_EDGE) => Some((4, 0)),
-            Tile::Shadow(ShadowFlags::S_EDGE) => Some((5, 0)),
-            Tile::Shadow(ShadowFlags::E_EDGE) => Some((0, 1)),
-            Tile::Shadow(ShadowFlags::W_EDGE) => Some((1, 1)),
-            Tile::Shadow(ShadowFlags::NE_CORNER) => Some((2, 1)),
-            Tile::Shadow(ShadowFlags::NW_CORNER) => Some((3, 1)),
-            Tile::Shadow(ShadowFlags::SE_CORNER) => Some((4, 1)),
-            Tile::Shadow(ShadowFlags::SW_CORNER) => Some((5, 1)),
-            Tile::Shadow(ShadowFlags { .. }) => None,
-        }
+        self.locations.get(&tile).cloned()
     }
 
     /// Returns the top-left corner coordinates of the tile corresponding
-    /// to the given position.
-    pub fn get_coordinates(&self, pos: &Position) -> (i32, i32) {
-        let x = self.width as i32 * pos.column();
+    /// to the given position on a grid.
+    pub fn get_coordinates(&self, grid: &dyn Grid, pos: &Position) -> (i32, i32) {
+        let x = self.width as i32 * grid.half_column(pos) / 2;
         let y = self.effective_height as i32 * pos.row();
         (x, y)
     }
 
+    /// Returns the position of the tile whose floor is drawn at the given
+    /// coordinates on a grid.
+    pub fn get_position(&self, grid: &dyn Grid, x: f64, y: f64) -> Position {
+        // The floor of a tile lies in the middle of its stacking part
+        let top = f64::from(self.height - self.effective_height) / 2.0;
+        let half_column = (2.0 * x / f64::from(self.width)).floor() as i32;
+        let row = ((y - top) / f64::from(self.effective_height)).floor() as i32;
+        grid.position(row, half_column)
+    }
+
     /// Returns the full size needed to draw a level of the given dimensions.
     pub fn get_rendering_size(&self, extents: (i32, i32)) -> (u32, u32) {
         let width = extents.0 as u32 * self.width;
@@ -134,38 +277,50 @@ impl<'a> Tileset<'a> {
     }
 }
 
-/// Enables selecting between two tilesets.
+/// Enables selecting between the tilesets of a theme.
 pub struct TilesetSelector<'a> {
-    /// The extents of the current level
-    extents: (i32, i32),
-    /// The big tileset
-    big_set: Tileset<'a>,
-    /// The small tileset
-    small_set: Tileset<'a>,
+    /// The index of the selected tileset
+    current: usize,
+    /// The tilesets, from the biggest to the smallest
+    sets: Vec<Tileset<'a>>,
 }
 
 impl<'a> TilesetSelector<'a> {
-    const THRESHOLD: i32 = 40;
-
     /// Creates a new instance.
-    pub fn new(big_set: Tileset<'a>, small_set: Tileset<'a>) -> Self {
-        TilesetSelector {
-            extents: (0, 0),
-            big_set,
-            small_set,
-        }
+    ///
+    /// # Panics
+    ///
+    /// Panics if no tileset is given.
+    pub fn new(mut sets: Vec<Tileset<'a>>) -> Self {
+        assert!(!sets.is_empty(), "At least one tileset is needed");
+        sets.sort_by_key(|set| cmp::Reverse(set.width()));
+        TilesetSelector { current: 0, sets }
     }
 
-    /// Resets the selector with the given extents.
-    pub fn reset(&mut self, extents: (i32, i32)) {
-        self.extents = extents;
+    /// Returns the tileset with the biggest tiles.
+    pub fn biggest(&self) -> &Tileset<'a> {
+        &self.sets[0]
     }
 
-    pub fn select(&self) -> &Tileset {
-        if cmp::max(self.extents.0, self.extents.1) > TilesetSelector::THRESHOLD {
-            &self.small_set
-        } else {
-            &self.big_set
-        }
+    /// Selects the best tileset to draw tiles of the given width.
+    ///
+    /// This is the smallest tileset that doesn't need to be scaled up,
+    /// or the biggest one if they all do.
+    pub fn reset(&mut self, tile_width: f64) {
+        self.current = self
+            .sets
+            .iter()
+            .rposition(|set| f64::from(set.width()) >= tile_width)
+            .unwrap_or(0);
+    }
+
+    /// Returns the selected tileset.
+    pub fn select(&self) -> &Tileset<'a> {
+        &self.sets[self.current]
+    }
+
+    /// Returns the selected tileset, for changing how it is drawn.
+    pub fn select_mut(&mut self) -> &mut Tileset<'a> {
+        &mut self.sets[self.current]
     }
 }